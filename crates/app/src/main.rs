@@ -1,3 +1,5 @@
+mod record;
+
 use anyhow::Result;
 use clap::Parser;
 use eframe::egui;
@@ -9,11 +11,13 @@ use pipewerk_ui::*;
 use hot_lib::*;
 use remoc::prelude::*;
 use ser::load_datastore;
-use std::net::Ipv4Addr;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tracing::debug;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use tracing_subscriber::{prelude::*, EnvFilter};
 
 #[cfg(feature = "reload")]
@@ -35,16 +39,58 @@ mod hot_lib {
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Subcommand>,
+
     /// Load content from a file
     #[clap(short, long)]
     load: Option<PathBuf>,
+
+    /// Maximum number of commands kept in the history, older ones are folded
+    #[clap(long, global = true)]
+    max_commands: Option<usize>,
+
+    /// Maximum age of the commands kept in the history, in seconds
+    #[clap(long, global = true)]
+    max_age: Option<u64>,
+}
+
+impl Args {
+    fn retention(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            max_commands: self.max_commands,
+            max_age: self.max_age.map(|s| s * 1_000_000_000),
+        }
+    }
+}
+
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// Record traces without a display, to be opened later with --load
+    Record {
+        /// File to save the recording to
+        output: PathBuf,
+
+        /// Time between two saves of the recording, in seconds
+        #[clap(long, default_value_t = 5)]
+        flush_interval: u64,
+    },
+}
+
+/// What the server forwards to the UI from its connections
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+    Connected(SessionId, SocketAddr),
+    Command(SessionId, TimedCommand),
+    Disconnected(SessionId),
 }
 
 pub struct App {
     data_store: DataStore,
     #[allow(dead_code)]
     rt: tokio::runtime::Runtime,
-    rx: tokio::sync::mpsc::Receiver<Command>,
+    rx: tokio::sync::mpsc::Receiver<ServerEvent>,
+    controls: tokio::sync::mpsc::Sender<(SessionId, ControlRequest)>,
     ui_state: UiState,
 }
 
@@ -62,47 +108,77 @@ impl App {
         });
 
         let (tx, rx) = tokio::sync::mpsc::channel(32);
+        let (controls, controls_rx) = tokio::sync::mpsc::channel(32);
 
         let rt = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .build()
             .unwrap();
 
-        rt.spawn(serve(tx));
+        rt.spawn(serve(tx, controls_rx));
 
-        let data_store = if let Some(path) = args.load {
-            load_datastore(&path).unwrap()
+        let mut data_store = if let Some(path) = &args.load {
+            load_datastore(path).unwrap()
         } else {
             DataStore::default()
         };
+        data_store.retention = args.retention();
 
         Self {
             data_store,
             rt,
             rx,
+            controls,
             ui_state: UiState::default(),
         }
     }
 
     pub fn recv_commands(&mut self, ctx: &egui::Context) {
-        while let Ok(cmd) = self.rx.try_recv() {
-            debug!("Received command: {cmd:?}");
-            self.data_store.record_command(cmd);
+        while let Ok(event) = self.rx.try_recv() {
+            handle_event(&mut self.data_store, event);
         }
 
         // FIXME this is a hack to make sure the update function is recalled
         ctx.request_repaint();
     }
+
+    fn send_controls(&mut self) {
+        for control in self.data_store.take_controls() {
+            if let Err(e) = self.controls.try_send(control) {
+                error!("Failed to send control: {e}");
+            }
+        }
+    }
 }
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         self.recv_commands(ctx);
         show_ui(&mut self.ui_state, &mut self.data_store, ctx, frame);
+        self.send_controls();
     }
 }
 
-pub async fn serve(tx: tokio::sync::mpsc::Sender<Command>) {
+pub fn handle_event(data_store: &mut DataStore, event: ServerEvent) {
+    match event {
+        ServerEvent::Connected(session, addr) => {
+            info!("Session {session:?} connected from {addr}");
+        }
+        ServerEvent::Command(session, cmd) => {
+            debug!("Received command: {cmd:?}");
+            data_store.record(session, cmd);
+        }
+        ServerEvent::Disconnected(session) => {
+            info!("Session {session:?} disconnected");
+            data_store.end_session(session);
+        }
+    }
+}
+
+pub async fn serve(
+    tx: tokio::sync::mpsc::Sender<ServerEvent>,
+    mut controls: tokio::sync::mpsc::Receiver<(SessionId, ControlRequest)>,
+) {
     // Going through tokio's mpsc because remoc's channel doesn't provide
     // sync methods, which is needed for the UI code
     info!(
@@ -115,26 +191,72 @@ pub async fn serve(tx: tokio::sync::mpsc::Sender<Command>) {
         .unwrap();
     debug!("Socket bound, waiting for connection");
 
+    // Controls are forwarded to the connection of their session
+    let mut sessions: HashMap<SessionId, tokio::sync::mpsc::Sender<ControlRequest>> =
+        HashMap::new();
+
+    // Session 0 is left to commands that are not coming from a connection
+    let mut next_session = 1;
     loop {
-        match listener.accept().await {
-            Ok((socket, _)) => {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            Some((session, control)) = controls.recv() => {
+                let sent = match sessions.get(&session) {
+                    Some(sender) => sender.send(control).await.is_ok(),
+                    None => false,
+                };
+                if !sent {
+                    warn!("Session {session:?} is not connected, dropping control");
+                    sessions.remove(&session);
+                }
+                continue;
+            }
+        };
+
+        match accepted {
+            Ok((socket, addr)) => {
+                let session = SessionId(next_session);
+                next_session += 1;
+
                 let (socket_rx, socket_tx) = socket.into_split();
-                let (conn, _, mut remote_rx): (
+                let (conn, mut remote_tx, mut remote_rx): (
                     _,
-                    rch::base::Sender<()>,
-                    rch::base::Receiver<Command>,
+                    rch::base::Sender<ControlRequest>,
+                    rch::base::Receiver<TimedCommand>,
                 ) = remoc::Connect::io(remoc::Cfg::default(), socket_rx, socket_tx)
                     .await
                     .unwrap();
                 tokio::spawn(conn);
                 debug!("Remoc connection established, waiting for events");
 
+                let (control_tx, mut control_rx) = tokio::sync::mpsc::channel(32);
+                sessions.insert(session, control_tx);
+                tokio::spawn(async move {
+                    while let Some(control) = control_rx.recv().await {
+                        if let Err(e) = remote_tx.send(control).await {
+                            error!("Error sending control to {addr}: {e}");
+                            break;
+                        }
+                    }
+                });
+
                 let tx = tx.clone();
                 tokio::spawn(async move {
-                    while let Some(cmd) = remote_rx.recv().await.unwrap() {
-                        debug!("Received command: {cmd:?}");
-                        let _ = tx.send(cmd).await;
+                    let _ = tx.send(ServerEvent::Connected(session, addr)).await;
+                    loop {
+                        match remote_rx.recv().await {
+                            Ok(Some(cmd)) => {
+                                debug!("Received command: {cmd:?}");
+                                let _ = tx.send(ServerEvent::Command(session, cmd)).await;
+                            }
+                            Ok(None) => break,
+                            Err(e) => {
+                                error!("Error receiving from {addr}: {e}");
+                                break;
+                            }
+                        }
                     }
+                    let _ = tx.send(ServerEvent::Disconnected(session)).await;
                 });
             }
             Err(e) => {
@@ -144,7 +266,7 @@ pub async fn serve(tx: tokio::sync::mpsc::Sender<Command>) {
     }
 }
 
-fn main() -> Result<(), eframe::Error> {
+fn main() -> Result<()> {
     let args = Args::parse();
 
     tracing_subscriber::registry()
@@ -152,10 +274,20 @@ fn main() -> Result<(), eframe::Error> {
         .with(EnvFilter::from_default_env())
         .init();
 
-    std::env::set_var(
-        "GST_DEBUG_DUMP_DOT_DIR",
-        std::env::current_dir().unwrap().canonicalize().unwrap(),
-    );
+    if let Some(Subcommand::Record {
+        output,
+        flush_interval,
+    }) = &args.command
+    {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        return rt.block_on(record::record(
+            output.clone(),
+            Duration::from_secs(*flush_interval),
+            args.retention(),
+        ));
+    }
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -181,6 +313,7 @@ fn main() -> Result<(), eframe::Error> {
             Ok(Box::new(App::new(cc, args)))
         }),
     )
+    .map_err(|e| anyhow::anyhow!("{e}"))
 }
 
 #[cfg(test)]
@@ -199,19 +332,46 @@ mod tests {
         let port = DEFAULT_PORT;
 
         let (server_tx, mut server_rx) = tokio::sync::mpsc::channel(12);
-        tokio::spawn(serve(server_tx));
+        let (controls_tx, controls_rx) = tokio::sync::mpsc::channel(12);
+        tokio::spawn(serve(server_tx, controls_rx));
 
         let (client_tx, _) = tokio::sync::broadcast::channel(12);
         let client_rx = client_tx.subscribe();
-        tokio::spawn(connect_client(ip, port, client_rx));
+        let (client_controls_tx, mut client_controls) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(connect_client(ip, port, client_rx, client_controls_tx));
 
         // Send a couple commands on client_tx, and compare them with server_rx
-        let command1 = Command::SpawnOrInsert(Entity::DANGLING, Node {}.into());
-        let command2 = Command::Remove(Entity::DANGLING, Remove::Edge);
+        let command1 = TimedCommand {
+            ts: 0,
+            wall_clock: 0,
+            command: Command::SpawnOrInsert(Entity::DANGLING, Node {}.into()),
+        };
+        let command2 = TimedCommand {
+            ts: 1,
+            wall_clock: 1,
+            command: Command::Remove(Entity::DANGLING, Remove::Edge),
+        };
         client_tx.send(command1.clone()).unwrap();
         client_tx.send(command2.clone()).unwrap();
 
-        assert_eq!(server_rx.recv().await.unwrap(), command1);
-        assert_eq!(server_rx.recv().await.unwrap(), command2);
+        let Some(ServerEvent::Connected(session, _)) = server_rx.recv().await else {
+            panic!("Expected a connection event");
+        };
+        assert_eq!(
+            server_rx.recv().await.unwrap(),
+            ServerEvent::Command(session, command1)
+        );
+        assert_eq!(
+            server_rx.recv().await.unwrap(),
+            ServerEvent::Command(session, command2)
+        );
+
+        // Controls go the other way, to the session they are addressed to
+        let request = ControlRequest {
+            entity: Entity::DANGLING,
+            control: Control::SendEos,
+        };
+        controls_tx.send((session, request.clone())).await.unwrap();
+        assert_eq!(client_controls.recv().await.unwrap(), request);
     }
 }
@@ -0,0 +1,45 @@
+use crate::{handle_event, serve};
+use anyhow::Result;
+use pipewerk_common::{recording::CommandWriter, DataStore, RetentionPolicy};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::info;
+
+/// Run the server without a display, appending what it receives to the
+/// recording at `output`, which is flushed periodically and once interrupted
+pub async fn record(
+    output: PathBuf,
+    flush_interval: Duration,
+    retention: RetentionPolicy,
+) -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+    // Nothing controls the traced processes while recording
+    let (_, controls) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(serve(tx, controls));
+
+    let mut data_store = DataStore::default();
+    data_store.retention = retention;
+    let mut writer = CommandWriter::create(&output)?;
+
+    let mut interval = tokio::time::interval(flush_interval);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    info!("Recording to {output:?}");
+    loop {
+        tokio::select! {
+            Some(event) = rx.recv() => {
+                handle_event(&mut data_store, event);
+                // Commands must be written before the retention policy folds them
+                writer.sync(&data_store)?;
+            }
+            _ = interval.tick() => writer.flush()?,
+            _ = &mut ctrl_c => {
+                info!("Interrupted, saving the recording");
+                break;
+            }
+        }
+    }
+
+    writer.flush()
+}
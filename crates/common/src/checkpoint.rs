@@ -0,0 +1,85 @@
+use crate::{AppendTo, Command, MessageLog, Properties, Remove, Snapshot, SpawnOrInsert};
+use hecs::Entity;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Number of recorded commands between two checkpoints
+pub const CHECKPOINT_INTERVAL: usize = 10_000;
+
+/// State of the world at some point of the history, folded from the commands
+/// recorded up to there. Entities are in the id space of the history, so that
+/// a checkpoint can be restored in place of replaying the commands.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    entities: HashMap<Entity, HashMap<Remove, SpawnOrInsert>>,
+}
+
+impl Checkpoint {
+    pub fn apply(&mut self, command: &Command) {
+        match command {
+            // Changes are folded into the properties, as when they are run
+            Command::SpawnOrInsert(entity, SpawnOrInsert::PropertyChange(change)) => {
+                let properties = self
+                    .entities
+                    .entry(*entity)
+                    .or_default()
+                    .entry(Remove::Properties)
+                    .or_insert_with(|| Properties::default().into());
+                if let SpawnOrInsert::Properties(properties) = properties {
+                    properties.apply(change.clone());
+                }
+            }
+            Command::SpawnOrInsert(entity, SpawnOrInsert::BusMessage(message)) => {
+                let log = self
+                    .entities
+                    .entry(*entity)
+                    .or_default()
+                    .entry(Remove::MessageLog)
+                    .or_insert_with(|| MessageLog::default().into());
+                if let SpawnOrInsert::MessageLog(log) = log {
+                    log.push(message.clone());
+                }
+            }
+            Command::SpawnOrInsert(entity, component) => {
+                self.entities
+                    .entry(*entity)
+                    .or_default()
+                    .insert(component.kind(), component.clone());
+            }
+            Command::Remove(entity, kind) => {
+                if let Some(components) = self.entities.get_mut(entity) {
+                    components.remove(kind);
+                }
+            }
+            Command::Despawn(entity) => {
+                self.entities.remove(entity);
+            }
+        }
+    }
+
+    /// Rebuild the state into an empty snapshot, as replaying the commands
+    /// folded into the checkpoint would
+    pub fn restore(&self, snapshot: &mut Snapshot) {
+        for (&entity, components) in self.entities.iter() {
+            // Entities can outlive all of their components
+            snapshot.remote_entities.map(entity, &mut snapshot.world);
+
+            for component in components.values() {
+                let mut command = Command::SpawnOrInsert(entity, component.clone());
+                command.translate_entities(&mut snapshot.remote_entities, &mut snapshot.world);
+                command.run_on(&mut snapshot.world);
+            }
+        }
+    }
+
+    /// Rebuild the state into an empty world, using the entities of the
+    /// history as they are
+    pub fn restore_in_place(&self, world: &mut hecs::World) {
+        for (&entity, components) in self.entities.iter() {
+            world.spawn_at(entity, ());
+            for component in components.values() {
+                component.clone().append_to(world, entity);
+            }
+        }
+    }
+}
@@ -1,12 +1,23 @@
 use crate::*;
 use hecs::Entity;
 use remoc::prelude::*;
+use std::cell::Cell;
 use std::net::Ipv4Addr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::net::TcpStream;
 use tracing::*;
 
+thread_local! {
+    static CURRENT_TIME: Cell<Option<Timestamp>> = const { Cell::new(None) };
+}
+
+#[derive(Clone)]
 pub struct RecordingStream {
-    pub tx: tokio::sync::broadcast::Sender<Command>,
+    pub tx: tokio::sync::broadcast::Sender<TimedCommand>,
+    start: Instant,
+    // Instant of the timestamp zero, known from the first time that is set
+    origin: Arc<OnceLock<Instant>>,
 }
 
 impl Default for RecordingStream {
@@ -21,13 +32,58 @@ impl RecordingStream {
         // from the broadcast channel. This avoids the overhead of spawning a new task
         // for each call.
         let (tx, _) = tokio::sync::broadcast::channel(2048);
-        Self { tx }
+        Self {
+            tx,
+            start: Instant::now(),
+            origin: Default::default(),
+        }
     }
 
-    pub fn connect(&self, ip: Ipv4Addr, port: u16) {
+    /// Set the timestamp of the commands sent from the current thread, until
+    /// it is set again. Threads that never set it use the current time on the
+    /// same clock, which must be monotonic.
+    pub fn set_time(&self, ts: Timestamp) {
+        CURRENT_TIME.with(|t| t.set(Some(ts)));
+        self.origin.get_or_init(|| {
+            let now = Instant::now();
+            now.checked_sub(Duration::from_nanos(ts)).unwrap_or(now)
+        });
+    }
+
+    /// Current time on the clock of the timestamps set so far, if any
+    pub fn now(&self) -> Option<Timestamp> {
+        Some(self.origin.get()?.elapsed().as_nanos() as Timestamp)
+    }
+
+    fn send(&self, command: Command) {
+        // Until a time is set, commands are stamped with the time elapsed
+        // since the stream was created
+        let ts = CURRENT_TIME
+            .with(|t| t.get())
+            .or_else(|| self.now())
+            .unwrap_or_else(|| self.start.elapsed().as_nanos() as Timestamp);
+        let wall_clock = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        let _ = self.tx.send(TimedCommand {
+            ts,
+            wall_clock,
+            command,
+        });
+    }
+
+    /// Connect to the app in the background, the controls it sends are
+    /// forwarded to the returned receiver
+    pub fn connect(
+        &self,
+        ip: Ipv4Addr,
+        port: u16,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<ControlRequest> {
         info!("Connecting to {ip}:{port}");
         let rx = self.tx.subscribe();
-        tokio::spawn(connect_client(ip, port, rx));
+        let (controls_tx, controls_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(connect_client(ip, port, rx, controls_tx));
+        controls_rx
     }
 
     pub fn insert_one(&self, id: Entity, component: impl Into<SpawnOrInsert>) {
@@ -36,34 +92,28 @@ impl RecordingStream {
             self.tx.receiver_count(),
             self.tx.len()
         );
-        let _ = self.tx.send(Command::SpawnOrInsert(id, component.into()));
+        self.send(Command::SpawnOrInsert(id, component.into()));
     }
 
-    pub fn remove_one<T>(&self, id: Entity) {
-        let component = match std::any::type_name::<T>() {
-            "Node" => Remove::Node,
-            "Edge" => Remove::Edge,
-            "State" => Remove::State,
-            "Name" => Remove::Name,
-            "TypeName" => Remove::TypeName,
-            "Properties" => Remove::Properties,
-            "Port" => Remove::Port,
-            "Child" => Remove::Child,
-            _ => panic!("Unsupported component type"),
-        };
-        let _ = self.tx.send(Command::Remove(id, component));
+    pub fn remove_one<T: Component>(&self, id: Entity) {
+        self.send(Command::Remove(id, T::KIND));
+    }
+
+    pub fn despawn(&self, id: Entity) {
+        self.send(Command::Despawn(id));
     }
 }
 
 pub async fn connect_client(
     ip: Ipv4Addr,
     port: u16,
-    mut rx: tokio::sync::broadcast::Receiver<Command>,
+    mut rx: tokio::sync::broadcast::Receiver<TimedCommand>,
+    controls: tokio::sync::mpsc::UnboundedSender<ControlRequest>,
 ) {
     let socket = TcpStream::connect((ip, port)).await.unwrap();
     info!("Connected to {ip}:{port}");
     let (socket_rx, socket_tx) = socket.into_split();
-    let (conn, mut remote_tx, _): (_, _, rch::base::Receiver<()>) =
+    let (conn, mut remote_tx, mut remote_rx): (_, _, rch::base::Receiver<ControlRequest>) =
         remoc::Connect::io(remoc::Cfg::default(), socket_rx, socket_tx)
             .await
             .unwrap();
@@ -71,8 +121,54 @@ pub async fn connect_client(
 
     info!("Connected to server, waiting for commands");
 
-    while let Ok(cmd) = rx.recv().await {
-        debug!("Forwarding: {cmd:?}");
-        remote_tx.send(cmd).await.unwrap();
+    let mut controls_open = true;
+    loop {
+        tokio::select! {
+            cmd = rx.recv() => match cmd {
+                Ok(cmd) => {
+                    debug!("Forwarding: {cmd:?}");
+                    remote_tx.send(cmd).await.unwrap();
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("Connection is lagging behind, {n} commands were dropped");
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            },
+            control = remote_rx.recv(), if controls_open => match control {
+                Ok(Some(control)) => {
+                    debug!("Received control: {control:?}");
+                    let _ = controls.send(control);
+                }
+                Ok(None) => controls_open = false,
+                Err(e) => {
+                    warn!("Failed to receive controls: {e}");
+                    controls_open = false;
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        let stream = RecordingStream::new();
+        assert_eq!(stream.now(), None);
+
+        // Set on another thread, timestamps are much smaller than the
+        // monotonic time
+        let clone = stream.clone();
+        std::thread::spawn(move || clone.set_time(1_000_000))
+            .join()
+            .unwrap();
+        let now = stream.now().unwrap();
+        assert!((1_000_000..1_000_000_000).contains(&now));
+
+        // The first time set is the reference
+        stream.set_time(0);
+        assert!(stream.now().unwrap() >= now);
     }
 }
@@ -1,6 +1,6 @@
 use derive_more::{Display, From};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use tracing::error;
 
 // It is necessary to put the component definitions in a separate crate
@@ -30,8 +30,130 @@ pub struct Name(pub String);
 #[derive(Debug, Display, PartialEq, Eq, Clone, Serialize, Deserialize, From)]
 pub struct TypeName(pub String);
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default, From)]
-pub struct Properties(pub HashMap<String, String>);
+/// Value of an element property
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(Option<String>),
+    Enum {
+        value: i32,
+        nick: String,
+    },
+    Flags {
+        value: u32,
+        nicks: Vec<String>,
+    },
+    Caps(Option<Caps>),
+    /// Serialized structure
+    Structure(Option<String>),
+    /// Name of the referenced object
+    Object(Option<String>),
+    /// Value of another type, as described by GLib
+    Other(String),
+}
+
+impl PropertyValue {
+    /// Numeric value, to sort and compare properties
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            PropertyValue::Bool(v) => Some(*v as u8 as f64),
+            PropertyValue::Int(v) => Some(*v as f64),
+            PropertyValue::UInt(v) => Some(*v as f64),
+            PropertyValue::Float(v) => Some(*v),
+            PropertyValue::Enum { value, .. } => Some(*value as f64),
+            PropertyValue::Flags { value, .. } => Some(*value as f64),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PropertyValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PropertyValue::Bool(v) => write!(f, "{v}"),
+            PropertyValue::Int(v) => write!(f, "{v}"),
+            PropertyValue::UInt(v) => write!(f, "{v}"),
+            PropertyValue::Float(v) => write!(f, "{v}"),
+            PropertyValue::String(Some(v))
+            | PropertyValue::Structure(Some(v))
+            | PropertyValue::Object(Some(v)) => write!(f, "{v}"),
+            PropertyValue::Enum { nick, .. } => write!(f, "{nick}"),
+            PropertyValue::Flags { nicks, .. } if nicks.is_empty() => write!(f, "(none)"),
+            PropertyValue::Flags { nicks, .. } => write!(f, "{}", nicks.join("+")),
+            PropertyValue::Caps(Some(caps)) => write!(f, "{}", caps.short_label()),
+            PropertyValue::String(None)
+            | PropertyValue::Structure(None)
+            | PropertyValue::Object(None)
+            | PropertyValue::Caps(None) => write!(f, "NULL"),
+            PropertyValue::Other(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+/// Description of a property, as declared by the element
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
+pub struct ParamSpec {
+    /// GLib type of the values, such as `gint` or `GstCaps`
+    pub type_name: String,
+    pub blurb: Option<String>,
+    pub default: Option<PropertyValue>,
+    /// Minimum and maximum of numeric properties
+    pub range: Option<(PropertyValue, PropertyValue)>,
+    /// Nicks of the possible values of enum and flags properties
+    pub choices: Vec<String>,
+    pub readable: bool,
+    pub writable: bool,
+    pub construct_only: bool,
+    pub controllable: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Property {
+    pub value: PropertyValue,
+    pub spec: ParamSpec,
+}
+
+impl Property {
+    /// Whether the value is the default one, properties without a known
+    /// default are considered as such
+    pub fn is_default(&self) -> bool {
+        self.spec
+            .default
+            .as_ref()
+            .is_none_or(|default| *default == self.value)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default, From)]
+pub struct Properties(pub BTreeMap<String, Property>);
+
+impl Properties {
+    /// Update the value of a property, properties that were not recorded yet
+    /// are added without their description
+    pub fn apply(&mut self, change: PropertyChange) {
+        match self.0.get_mut(&change.name) {
+            Some(property) => property.value = change.value,
+            None => {
+                let property = Property {
+                    value: change.value,
+                    spec: ParamSpec::default(),
+                };
+                self.0.insert(change.name, property);
+            }
+        }
+    }
+}
+
+/// New value of a single property, merged into the `Properties` of the
+/// entity rather than stored on its own
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PropertyChange {
+    pub name: String,
+    pub value: PropertyValue,
+}
 
 // FIXME separate in InputPort and OutputPort types
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
@@ -54,6 +176,358 @@ pub struct Child {
     pub parent: hecs::Entity,
 }
 
+/// Buffer flow through a port, totals are counted since the port was first
+/// seen and rates are averaged over the last reporting period
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize, Default)]
+pub struct PadStats {
+    pub buffers: u64,
+    pub bytes: u64,
+    pub buffers_per_sec: f64,
+    pub bytes_per_sec: f64,
+    /// Presentation timestamp of the last buffer in nanoseconds
+    pub last_pts: Option<u64>,
+}
+
+/// A single structure of a caps, with its fields serialized as strings
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+pub struct CapsStructure {
+    pub name: String,
+    pub fields: Vec<(String, String)>,
+}
+
+impl CapsStructure {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Media format of a port or of the link between two ports
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default, From)]
+pub struct Caps(pub Vec<CapsStructure>);
+
+impl Caps {
+    /// Short description of the first structure, such as `video/x-raw, NV12, 1920x1080`
+    pub fn short_label(&self) -> String {
+        let Some(s) = self.0.first() else {
+            return "EMPTY".to_string();
+        };
+
+        let mut parts = vec![s.name.clone()];
+        if let Some(format) = s.field("format") {
+            parts.push(format.to_string());
+        }
+        if let (Some(width), Some(height)) = (s.field("width"), s.field("height")) {
+            parts.push(format!("{width}x{height}"));
+        }
+        if let Some(rate) = s.field("rate") {
+            parts.push(format!("{rate} Hz"));
+        }
+        if let Some(channels) = s.field("channels") {
+            parts.push(format!("{channels} ch"));
+        }
+        if let Some(encoding) = s.field("encoding-name") {
+            parts.push(encoding.to_string());
+        }
+        if self.0.len() > 1 {
+            parts.push(format!("+{}", self.0.len() - 1));
+        }
+        parts.join(", ")
+    }
+}
+
+/// A traced process, parent of its top level elements
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+pub struct Session {
+    pub process_name: String,
+    pub pid: u32,
+    pub host: String,
+    /// Unix time of the connection, in nanoseconds
+    pub connected_at: u64,
+}
+
+/// Distribution of the latencies measured over the last reporting period, in
+/// nanoseconds
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize, Default)]
+pub struct LatencyStats {
+    pub samples: u64,
+    pub min: u64,
+    pub mean: u64,
+    pub max: u64,
+    pub p99: u64,
+}
+
+impl LatencyStats {
+    pub fn from_samples(samples: &mut [u64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let count = samples.len();
+        let sum: u128 = samples.iter().map(|&s| s as u128).sum();
+        Some(Self {
+            samples: count as u64,
+            min: samples[0],
+            mean: (sum / count as u128) as u64,
+            max: samples[count - 1],
+            p99: samples[(count * 99).div_ceil(100) - 1],
+        })
+    }
+}
+
+/// Time buffers spend in an element, from entering one of its sink pads to
+/// leaving one of its source pads with the same PTS
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize, From)]
+pub struct ProcessingLatency(pub LatencyStats);
+
+/// Time buffers took to reach a sink since they left their source, measured
+/// on the sink
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize, From)]
+pub struct EndToEndLatency(pub LatencyStats);
+
+/// An OS thread of a traced process
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+pub struct ThreadInfo {
+    pub tid: u64,
+    /// Name of the thread, streaming threads are named after the pad of
+    /// their task
+    pub name: String,
+}
+
+/// Thread last seen pushing buffers out of a pad, an entity with a
+/// `ThreadInfo`
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub struct Thread {
+    pub thread: hecs::Entity,
+}
+
+/// CPU time used by a process, a thread or the elements running on threads,
+/// over the last reporting period
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize, Default)]
+pub struct CpuUsage {
+    /// Share of a single core, 100 when a core is fully used
+    pub percent: f64,
+    /// CPU time used so far, in nanoseconds
+    pub total: u64,
+}
+
+/// Fill level of a queue against its limits, limits set to 0 are disabled.
+/// Times are in nanoseconds.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize, Default)]
+pub struct QueueLevel {
+    pub buffers: u64,
+    pub bytes: u64,
+    pub time: u64,
+    pub max_buffers: u64,
+    pub max_bytes: u64,
+    pub max_time: u64,
+    /// How long the queue has been full, 0 when it is not
+    pub full_for: u64,
+    /// How long the queue has been empty, 0 when it is not
+    pub empty_for: u64,
+}
+
+impl QueueLevel {
+    /// Fill ratio against the closest limit, between 0 and 1
+    pub fn fill(&self) -> f64 {
+        [
+            (self.buffers, self.max_buffers),
+            (self.bytes, self.max_bytes),
+            (self.time, self.max_time),
+        ]
+        .into_iter()
+        .filter(|&(_, max)| max > 0)
+        .map(|(level, max)| (level as f64 / max as f64).min(1.0))
+        .fold(0.0, f64::max)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.fill() >= 1.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers == 0
+    }
+}
+
+/// Set by the watchdog of the tracer on pads that stopped seeing buffers
+/// while their element is playing, and on elements stuck in a state change.
+/// Removed once they recover.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub struct Stalled {
+    pub reason: StallReason,
+    /// Time since which the pad has seen no buffers while playing, or the
+    /// element has been changing state
+    pub since: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum StallReason {
+    NoBuffers,
+    /// Still changing to the given state
+    PendingState(State),
+}
+
+/// Sample of the buffers going through a pad, sent while the sampling of the
+/// pad is turned on from the app. Previews are only shown live, they are not
+/// kept in the history.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum Preview {
+    /// Frame downscaled to fit in a thumbnail, as RGBA pixels
+    Video {
+        width: u32,
+        height: u32,
+        rgba: Vec<u8>,
+    },
+    /// Peak level of each channel over consecutive slices of the buffer,
+    /// interleaved and between 0 and 1
+    Audio { channels: u32, peaks: Vec<f32> },
+    /// First bytes of buffers that can't be displayed otherwise
+    Data { size: u64, head: Vec<u8> },
+}
+
+/// Graph of a bin as dumped by GStreamer on request, in the DOT format
+#[derive(Debug, Display, PartialEq, Eq, Clone, Serialize, Deserialize, From)]
+pub struct DotGraph(pub String);
+
+/// Result of a latency query requested on an element, in nanoseconds
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub struct QueriedLatency {
+    pub live: bool,
+    pub min: u64,
+    /// Unbounded when `None`
+    pub max: Option<u64>,
+}
+
+/// Kinds of bus messages recorded, the others are ignored
+#[derive(Debug, Display, PartialEq, Eq, Copy, Clone, Hash, Serialize, Deserialize)]
+pub enum MessageKind {
+    Error,
+    Warning,
+    Info,
+    Qos,
+    Latency,
+    Buffering,
+    StateChanged,
+}
+
+/// Message posted on the bus by an element, counted in the `MessageLog` of
+/// the element rather than stored on its own
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct BusMessage {
+    pub kind: MessageKind,
+    /// Path of the object that posted the message, such as `pipeline0/src`
+    pub source: String,
+    pub text: String,
+    /// Debug details of errors, warnings and infos
+    pub details: Option<String>,
+}
+
+/// Summary of the messages posted by an element, the messages themselves are
+/// found in the history
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+pub struct MessageLog {
+    pub errors: u32,
+    pub warnings: u32,
+    /// Last error or warning
+    pub last_issue: Option<BusMessage>,
+}
+
+impl MessageLog {
+    pub fn push(&mut self, message: BusMessage) {
+        match message.kind {
+            MessageKind::Error => self.errors += 1,
+            MessageKind::Warning => self.warnings += 1,
+            _ => return,
+        }
+        self.last_issue = Some(message);
+    }
+}
+
+/// Short text displayed under the name of a node
+#[derive(Debug, Display, PartialEq, Eq, Clone, Serialize, Deserialize, From)]
+pub struct Label(pub String);
+
+/// What a node stands for in the framework it comes from, the generic
+/// counterpart of `TypeName`
+#[derive(Debug, Display, PartialEq, Eq, Clone, Serialize, Deserialize, From)]
+pub struct Kind(pub String);
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, From)]
+pub enum AttributeValue {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Text(String),
+}
+
+impl From<&str> for AttributeValue {
+    fn from(s: &str) -> Self {
+        AttributeValue::Text(s.to_string())
+    }
+}
+
+impl std::fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributeValue::Bool(v) => write!(f, "{v}"),
+            AttributeValue::Int(v) => write!(f, "{v}"),
+            AttributeValue::UInt(v) => write!(f, "{v}"),
+            AttributeValue::Float(v) => write!(f, "{v:.3}"),
+            AttributeValue::Text(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Attribute {
+    pub value: AttributeValue,
+    /// Unit of the value, such as `ms` or `frames/s`
+    pub unit: Option<String>,
+}
+
+impl std::fmt::Display for Attribute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.unit {
+            Some(unit) => write!(f, "{} {unit}", self.value),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+/// Arbitrary values attached to an entity by clients that are not covered by
+/// the other components, they are listed in the inspector
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default, From)]
+pub struct Attributes(pub BTreeMap<String, Attribute>);
+
+impl Attributes {
+    pub fn with(self, key: impl Into<String>, value: impl Into<AttributeValue>) -> Self {
+        self.with_attribute(key, value.into(), None)
+    }
+
+    pub fn with_unit(
+        self,
+        key: impl Into<String>,
+        value: impl Into<AttributeValue>,
+        unit: impl Into<String>,
+    ) -> Self {
+        self.with_attribute(key, value.into(), Some(unit.into()))
+    }
+
+    fn with_attribute(
+        mut self,
+        key: impl Into<String>,
+        value: AttributeValue,
+        unit: Option<String>,
+    ) -> Self {
+        self.0.insert(key.into(), Attribute { value, unit });
+        self
+    }
+}
+
 // Re-export Layers from graph-layout as a type alias for easier migration
 pub type Layers = graph_layout::Layers<hecs::Entity>;
 
@@ -94,4 +568,260 @@ pub mod gstreamer {
             }
         }
     }
+
+    impl From<State> for gst::State {
+        fn from(state: State) -> Self {
+            match state {
+                State::Null => gst::State::Null,
+                State::Ready => gst::State::Ready,
+                State::Paused => gst::State::Paused,
+                State::Playing => gst::State::Playing,
+                // States of links
+                State::Pending | State::Done | State::Failed => gst::State::VoidPending,
+            }
+        }
+    }
+
+    impl From<&gst::CapsRef> for Caps {
+        fn from(caps: &gst::CapsRef) -> Self {
+            use gst::prelude::*;
+
+            if caps.is_any() {
+                return Caps(vec![CapsStructure {
+                    name: "ANY".to_string(),
+                    fields: Vec::new(),
+                }]);
+            }
+
+            caps.iter()
+                .map(|s| CapsStructure {
+                    name: s.name().to_string(),
+                    fields: s
+                        .iter()
+                        .map(|(name, value)| {
+                            let value = value
+                                .serialize()
+                                .map(|v| v.to_string())
+                                .unwrap_or_else(|_| format!("{value:?}"));
+                            (name.to_string(), value)
+                        })
+                        .collect(),
+                })
+                .collect::<Vec<_>>()
+                .into()
+        }
+    }
+
+    impl From<&glib::Value> for PropertyValue {
+        fn from(value: &glib::Value) -> Self {
+            use glib::Type;
+            use gst::prelude::*;
+
+            match value.type_() {
+                Type::BOOL => PropertyValue::Bool(value.get().unwrap_or_default()),
+                Type::I8 => PropertyValue::Int(value.get::<i8>().unwrap_or_default() as i64),
+                Type::I32 => PropertyValue::Int(value.get::<i32>().unwrap_or_default() as i64),
+                Type::I_LONG => {
+                    PropertyValue::Int(value.get::<glib::ILong>().map_or(0, |v| v.0 as i64))
+                }
+                Type::I64 => PropertyValue::Int(value.get().unwrap_or_default()),
+                Type::U8 => PropertyValue::UInt(value.get::<u8>().unwrap_or_default() as u64),
+                Type::U32 => PropertyValue::UInt(value.get::<u32>().unwrap_or_default() as u64),
+                Type::U_LONG => {
+                    PropertyValue::UInt(value.get::<glib::ULong>().map_or(0, |v| v.0 as u64))
+                }
+                Type::U64 => PropertyValue::UInt(value.get().unwrap_or_default()),
+                Type::F32 => PropertyValue::Float(value.get::<f32>().unwrap_or_default() as f64),
+                Type::F64 => PropertyValue::Float(value.get().unwrap_or_default()),
+                Type::STRING => PropertyValue::String(value.get::<Option<String>>().ok().flatten()),
+                t if t.is_a(Type::ENUM) => match glib::EnumValue::from_value(value) {
+                    Some((_, v)) => PropertyValue::Enum {
+                        value: v.value(),
+                        nick: v.nick().to_string(),
+                    },
+                    None => PropertyValue::Other(format!("{value:?}")),
+                },
+                t if t.is_a(Type::FLAGS) => match glib::FlagsValue::from_value(value) {
+                    Some((_, values)) => PropertyValue::Flags {
+                        value: values.iter().fold(0, |acc, v| acc | v.value()),
+                        nicks: values.iter().map(|v| v.nick().to_string()).collect(),
+                    },
+                    None => PropertyValue::Other(format!("{value:?}")),
+                },
+                t if t.is_a(gst::Caps::static_type()) => PropertyValue::Caps(
+                    value
+                        .get::<Option<gst::Caps>>()
+                        .ok()
+                        .flatten()
+                        .map(|caps| Caps::from(caps.as_ref())),
+                ),
+                t if t.is_a(gst::Structure::static_type()) => PropertyValue::Structure(
+                    value
+                        .get::<Option<gst::Structure>>()
+                        .ok()
+                        .flatten()
+                        .map(|s| s.to_string()),
+                ),
+                t if t.is_a(Type::OBJECT) => {
+                    PropertyValue::Object(value.get::<Option<glib::Object>>().ok().flatten().map(
+                        |object| match object.downcast_ref::<gst::Object>() {
+                            Some(object) => object.name().to_string(),
+                            None => object.type_().name().to_string(),
+                        },
+                    ))
+                }
+                _ => PropertyValue::Other(
+                    value
+                        .serialize()
+                        .map(|v| v.to_string())
+                        .unwrap_or_else(|_| format!("{value:?}")),
+                ),
+            }
+        }
+    }
+
+    impl From<&glib::ParamSpec> for ParamSpec {
+        fn from(pspec: &glib::ParamSpec) -> Self {
+            let flags = pspec.flags();
+            Self {
+                type_name: pspec.value_type().name().to_string(),
+                blurb: pspec.blurb().map(str::to_string),
+                default: Some(pspec.default_value().into()),
+                range: range(pspec),
+                choices: choices(pspec),
+                readable: flags.contains(glib::ParamFlags::READABLE),
+                writable: flags.contains(glib::ParamFlags::WRITABLE),
+                construct_only: flags.contains(glib::ParamFlags::CONSTRUCT_ONLY),
+                controllable: flags.contains(gst::PARAM_FLAG_CONTROLLABLE),
+            }
+        }
+    }
+
+    fn range(pspec: &glib::ParamSpec) -> Option<(PropertyValue, PropertyValue)> {
+        macro_rules! range {
+            ($($spec:ty => $variant:ident as $t:ty),+) => {
+                $(
+                    if let Some(p) = pspec.downcast_ref::<$spec>() {
+                        return Some((
+                            PropertyValue::$variant(p.minimum() as $t),
+                            PropertyValue::$variant(p.maximum() as $t),
+                        ));
+                    }
+                )+
+            };
+        }
+
+        range!(
+            glib::ParamSpecChar => Int as i64,
+            glib::ParamSpecInt => Int as i64,
+            glib::ParamSpecLong => Int as i64,
+            glib::ParamSpecInt64 => Int as i64,
+            glib::ParamSpecUChar => UInt as u64,
+            glib::ParamSpecUInt => UInt as u64,
+            glib::ParamSpecULong => UInt as u64,
+            glib::ParamSpecUInt64 => UInt as u64,
+            glib::ParamSpecFloat => Float as f64,
+            glib::ParamSpecDouble => Float as f64
+        );
+        None
+    }
+
+    fn choices(pspec: &glib::ParamSpec) -> Vec<String> {
+        if let Some(p) = pspec.downcast_ref::<glib::ParamSpecEnum>() {
+            p.enum_class()
+                .values()
+                .iter()
+                .map(|v| v.nick().to_string())
+                .collect()
+        } else if let Some(p) = pspec.downcast_ref::<glib::ParamSpecFlags>() {
+            p.flags_class()
+                .values()
+                .iter()
+                .map(|v| v.nick().to_string())
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caps_short_label() {
+        let caps = Caps(vec![CapsStructure {
+            name: "video/x-raw".to_string(),
+            fields: vec![
+                ("format".to_string(), "NV12".to_string()),
+                ("width".to_string(), "1920".to_string()),
+                ("height".to_string(), "1080".to_string()),
+                ("framerate".to_string(), "30/1".to_string()),
+            ],
+        }]);
+        assert_eq!(caps.short_label(), "video/x-raw, NV12, 1920x1080");
+        assert_eq!(Caps::default().short_label(), "EMPTY");
+    }
+
+    #[test]
+    fn test_attributes() {
+        let attributes = Attributes::default()
+            .with("queued", 3u64)
+            .with_unit("latency", 12.5, "ms")
+            .with("backend", "cuda");
+        let formatted: Vec<String> = attributes
+            .0
+            .iter()
+            .map(|(key, attribute)| format!("{key}: {attribute}"))
+            .collect();
+        assert_eq!(
+            formatted,
+            ["backend: cuda", "latency: 12.500 ms", "queued: 3"]
+        );
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        assert_eq!(LatencyStats::from_samples(&mut []), None);
+
+        let mut samples: Vec<u64> = (1..=200).rev().collect();
+        let stats = LatencyStats::from_samples(&mut samples).unwrap();
+        assert_eq!(
+            stats,
+            LatencyStats {
+                samples: 200,
+                min: 1,
+                mean: 100,
+                max: 200,
+                p99: 198,
+            }
+        );
+
+        let stats = LatencyStats::from_samples(&mut [7]).unwrap();
+        assert_eq!((stats.min, stats.p99, stats.max), (7, 7, 7));
+    }
+
+    #[test]
+    fn test_queue_fill() {
+        let level = QueueLevel {
+            buffers: 50,
+            bytes: 1_000,
+            time: 900_000_000,
+            max_buffers: 200,
+            max_bytes: 0,
+            max_time: 1_000_000_000,
+            ..Default::default()
+        };
+        assert_eq!(level.fill(), 0.9);
+        assert!(!level.is_full() && !level.is_empty());
+
+        let level = QueueLevel {
+            buffers: 200,
+            ..level
+        };
+        assert!(level.is_full());
+        assert_eq!(QueueLevel::default().fill(), 0.0);
+        assert!(QueueLevel::default().is_empty());
+    }
 }
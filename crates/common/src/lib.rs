@@ -1,23 +1,115 @@
+#[macro_use]
+mod macros;
+
+pub mod checkpoint;
 pub mod client;
 pub mod comps;
+pub mod recording;
 pub mod ser;
 
 pub use client::RecordingStream;
 pub use comps::*;
 
+use checkpoint::{Checkpoint, CHECKPOINT_INTERVAL};
 use enum_dispatch::enum_dispatch;
 use hecs::Entity;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
+use tracing::debug;
 
 pub const DEFAULT_PORT: u16 = 9870;
 
+/// Time in nanoseconds, relative to the start of the traced process
 pub type Timestamp = u64;
 
+/// A command as sent over the wire, stamped by the sender
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimedCommand {
+    pub ts: Timestamp,
+    /// Unix time at which the command was sent, in nanoseconds
+    pub wall_clock: u64,
+    pub command: Command,
+}
+
+/// Identifies a connection, each one has its own entity namespace
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct SessionId(pub u64);
+
+/// Action requested by the app on an object of a traced process
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Control {
+    SetProperty(PropertyChange),
+    SetState(State),
+    SendEos,
+    Flush,
+    /// Answered with a `DotGraph`
+    DumpDot,
+    /// Answered with a `QueriedLatency`
+    QueryLatency,
+    /// Turn the sampling of the buffers of a pad on or off, samples are
+    /// answered with `Preview`
+    SamplePad(bool),
+}
+
+/// A control as sent over the wire, addressed to an entity of the receiver
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ControlRequest {
+    pub entity: Entity,
+    pub control: Control,
+}
+
+/// Entities of a sender mapped to the local ones, in both directions
+#[derive(Default)]
+pub struct EntityMap {
+    local: HashMap<Entity, Entity>,
+    remote: HashMap<Entity, Entity>,
+}
+
+impl EntityMap {
+    /// Local entity of a remote one, reserved in the world the first time
+    /// it is seen
+    pub fn map(&mut self, remote: Entity, world: &mut hecs::World) -> Entity {
+        *self.local.entry(remote).or_insert_with(|| {
+            let local = world.reserve_entity();
+            self.remote.insert(local, remote);
+            local
+        })
+    }
+
+    pub fn local(&self, remote: Entity) -> Option<Entity> {
+        self.local.get(&remote).copied()
+    }
+
+    pub fn remote(&self, local: Entity) -> Option<Entity> {
+        self.remote.get(&local).copied()
+    }
+
+    /// Forget a remote entity, returning the local one it was mapped to
+    pub fn remove(&mut self, remote: Entity) -> Option<Entity> {
+        let local = self.local.remove(&remote)?;
+        self.remote.remove(&local);
+        Some(local)
+    }
+
+    /// Pairs of remote and local entities
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+        self.local.iter().map(|(&remote, &local)| (remote, local))
+    }
+}
+
+/// Entities of a session as known by the rolling snapshot
+#[derive(Default)]
+struct Namespace {
+    remote_entities: EntityMap,
+    /// Unix time corresponding to the timestamp zero of the session
+    wall_clock_origin: Option<u64>,
+}
+
 #[derive(Default)]
 pub struct Snapshot {
     pub world: hecs::World,
-    pub remote_entities: HashMap<Entity, Entity>,
+    pub remote_entities: EntityMap,
 }
 
 impl Snapshot {
@@ -28,6 +120,8 @@ impl Snapshot {
 
 #[derive(Serialize, Deserialize)]
 pub struct DataStore {
+    /// Latest state, its entities are the ones the history refers to. They
+    /// are mapped from the remote ones by the namespace of each session.
     #[serde(skip)]
     pub rolling_snapshot: Snapshot,
     #[serde(skip)]
@@ -35,6 +129,70 @@ pub struct DataStore {
     #[serde(skip)]
     pub current_view_mode: ViewMode,
     pub command_history: BTreeMap<Timestamp, Vec<Command>>,
+    /// Unix time corresponding to the timestamp zero, in nanoseconds
+    #[serde(default)]
+    pub wall_clock_origin: Option<u64>,
+    /// State folded from the commands dropped from the history
+    #[serde(default)]
+    pub base: Checkpoint,
+    #[serde(skip)]
+    pub retention: RetentionPolicy,
+    /// Folded states of the history, keyed by the last timestamp they include
+    #[serde(skip)]
+    checkpoints: BTreeMap<Timestamp, Checkpoint>,
+    #[serde(skip)]
+    commands_since_checkpoint: usize,
+    #[serde(skip)]
+    command_count: usize,
+    #[serde(skip)]
+    index: HistoryIndex,
+    #[serde(skip)]
+    namespaces: HashMap<SessionId, Namespace>,
+    /// Controls waiting to be sent to their session
+    #[serde(skip)]
+    controls: Vec<(SessionId, ControlRequest)>,
+}
+
+/// Timestamps of the history holding commands that are looked up on every
+/// frame, so that they are found without going through the whole history
+#[derive(Default)]
+struct HistoryIndex {
+    messages: BTreeSet<Timestamp>,
+    stalls: BTreeSet<Timestamp>,
+    // By entity of the history
+    property_changes: HashMap<Entity, BTreeSet<Timestamp>>,
+}
+
+impl HistoryIndex {
+    fn insert(&mut self, ts: Timestamp, command: &Command) {
+        let times = match command {
+            Command::SpawnOrInsert(_, SpawnOrInsert::BusMessage(_)) => &mut self.messages,
+            Command::SpawnOrInsert(_, SpawnOrInsert::Stalled(_)) => &mut self.stalls,
+            Command::SpawnOrInsert(entity, SpawnOrInsert::PropertyChange(_)) => {
+                self.property_changes.entry(*entity).or_default()
+            }
+            _ => return,
+        };
+        times.insert(ts);
+    }
+
+    /// Forget the timestamps up to `ts` included, once folded into the base
+    fn fold(&mut self, ts: Timestamp) {
+        self.messages = self.messages.split_off(&(ts + 1));
+        self.stalls = self.stalls.split_off(&(ts + 1));
+        self.property_changes.retain(|_, times| {
+            *times = times.split_off(&(ts + 1));
+            !times.is_empty()
+        });
+    }
+}
+
+/// Limits past which the oldest commands are folded into the base state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionPolicy {
+    pub max_commands: Option<usize>,
+    /// Maximum age of the commands relative to the latest one, in nanoseconds
+    pub max_age: Option<Timestamp>,
 }
 
 impl Default for DataStore {
@@ -44,6 +202,15 @@ impl Default for DataStore {
             fixed_snapshot: Snapshot::default(),
             current_view_mode: ViewMode::Rolling,
             command_history: BTreeMap::new(),
+            wall_clock_origin: None,
+            base: Checkpoint::default(),
+            retention: RetentionPolicy::default(),
+            checkpoints: BTreeMap::new(),
+            commands_since_checkpoint: 0,
+            command_count: 0,
+            index: HistoryIndex::default(),
+            namespaces: HashMap::new(),
+            controls: Vec::new(),
         }
     }
 }
@@ -56,18 +223,76 @@ pub enum ViewMode {
 }
 
 impl DataStore {
-    pub fn record_command(&mut self, mut command: Command) {
+    pub fn record(&mut self, session: SessionId, command: TimedCommand) {
+        // Sessions start at different times, their timestamps are moved on
+        // the timeline of the first one using the wall clock
+        let origin = command.wall_clock.saturating_sub(command.ts);
+        let namespace = self.namespaces.entry(session).or_default();
+        let session_origin = *namespace.wall_clock_origin.get_or_insert(origin);
+        let store_origin = *self.wall_clock_origin.get_or_insert(session_origin);
+        let ts = (command.ts + session_origin).saturating_sub(store_origin);
+
+        self.record_session_command(session, ts, command.command);
+    }
+
+    /// Record a command in the namespace of the default session
+    pub fn record_command(&mut self, ts: Timestamp, command: Command) {
+        self.record_session_command(SessionId::default(), ts, command);
+    }
+
+    pub fn record_session_command(
+        &mut self,
+        session: SessionId,
+        ts: Timestamp,
+        mut command: Command,
+    ) {
+        let namespace = self.namespaces.entry(session).or_default();
+
+        // Objects that were never sent to us can still be destroyed on the
+        // other side, there is no point in keeping track of those
+        if let Command::Despawn(entity) = command {
+            if namespace.remote_entities.local(entity).is_none() {
+                debug!("Ignoring despawn of unknown entity {entity:?}");
+                return;
+            }
+        }
+
         command.translate_entities(
-            &mut self.rolling_snapshot.remote_entities,
+            &mut namespace.remote_entities,
             &mut self.rolling_snapshot.world,
         );
 
-        let timestamp = if self.command_history.is_empty() {
-            0
-        } else {
-            self.command_history.keys().next_back().unwrap() + 1
+        // Components are sent again whenever an object is seen, only keep
+        // the commands that actually change something
+        if command.is_noop(&self.rolling_snapshot.world) {
+            return;
+        }
+
+        if command.is_transient() {
+            command.run_on(&mut self.rolling_snapshot.world);
+            return;
+        }
+
+        // Commands stamped on different threads can arrive slightly out of
+        // order, the history must follow the order they were applied in
+        let timestamp = match self.command_history.keys().next_back() {
+            Some(&last) => ts.max(last),
+            None => ts,
         };
 
+        // Checkpoints are only taken on complete timestamps, as later commands
+        // can still be appended to the last one
+        if self.commands_since_checkpoint >= CHECKPOINT_INTERVAL {
+            if let Some(&last) = self.command_history.keys().next_back() {
+                if timestamp > last {
+                    self.take_checkpoint(last);
+                }
+            }
+        }
+        self.commands_since_checkpoint += 1;
+        self.command_count += 1;
+
+        self.index.insert(timestamp, &command);
         self.command_history
             .entry(timestamp)
             .or_default()
@@ -75,6 +300,50 @@ impl DataStore {
 
         // Always update rolling snapshot
         command.run_on(&mut self.rolling_snapshot.world);
+
+        self.compact();
+    }
+
+    /// Forget the namespace of a session once its connection is closed, the
+    /// entities it created are kept
+    pub fn end_session(&mut self, session: SessionId) {
+        self.namespaces.remove(&session);
+    }
+
+    /// Fold the oldest commands into the base state until the history fits
+    /// in the retention policy
+    fn compact(&mut self) {
+        let Some(&latest) = self.command_history.keys().next_back() else {
+            return;
+        };
+
+        let mut folded = None;
+        while let Some(entry) = self.command_history.first_entry() {
+            let ts = *entry.key();
+            let too_many = self
+                .retention
+                .max_commands
+                .is_some_and(|max| self.command_count > max);
+            let too_old = self.retention.max_age.is_some_and(|max| latest - ts > max);
+            // Commands can still be appended to the latest timestamp
+            if !(too_many || too_old) || ts == latest {
+                break;
+            }
+
+            let commands = entry.remove();
+            self.command_count -= commands.len();
+            match self.checkpoints.remove(&ts) {
+                Some(checkpoint) => self.base = checkpoint,
+                None => commands.iter().for_each(|command| self.base.apply(command)),
+            }
+            folded = Some(ts);
+        }
+
+        // Checkpoints of the folded commands are included in the base
+        if let Some(ts) = folded {
+            self.checkpoints = self.checkpoints.split_off(&ts);
+            self.index.fold(ts);
+        }
     }
 
     pub fn current_timeline_position(&self) -> Option<Timestamp> {
@@ -169,11 +438,25 @@ impl DataStore {
                 // Extract layout data before rebuilding, keyed by remote entity ID
                 let preserved_layout = self.extract_layout_by_remote_id();
 
-                // Rebuild fixed snapshot up to the specified timestamp
+                // Rebuild fixed snapshot up to the specified timestamp, starting
+                // from the closest checkpoint
                 self.fixed_snapshot = Snapshot::new();
+                let start = match self.checkpoints.range(..=timestamp).next_back() {
+                    Some((&checkpoint_ts, checkpoint)) => {
+                        checkpoint.restore(&mut self.fixed_snapshot);
+                        Bound::Excluded(checkpoint_ts)
+                    }
+                    None => {
+                        self.base.restore(&mut self.fixed_snapshot);
+                        Bound::Unbounded
+                    }
+                };
 
-                // Apply all commands up to and including the specified timestamp
-                for (_, commands) in self.command_history.range(..=timestamp) {
+                // Apply the remaining commands up to and including the specified timestamp
+                for (_, commands) in self
+                    .command_history
+                    .range((start, Bound::Included(timestamp)))
+                {
                     for mut command in commands.iter().cloned() {
                         command.translate_entities(
                             &mut self.fixed_snapshot.remote_entities,
@@ -189,20 +472,81 @@ impl DataStore {
         }
     }
 
+    /// Fold the history up to and including `timestamp`, starting from the
+    /// closest checkpoint
+    fn fold_history(&self, timestamp: Timestamp) -> Checkpoint {
+        let (start, mut checkpoint) = match self.checkpoints.range(..=timestamp).next_back() {
+            Some((&ts, checkpoint)) => (Bound::Excluded(ts), checkpoint.clone()),
+            None => (Bound::Unbounded, self.base.clone()),
+        };
+        for (_, commands) in self
+            .command_history
+            .range((start, Bound::Included(timestamp)))
+        {
+            commands
+                .iter()
+                .for_each(|command| checkpoint.apply(command));
+        }
+        checkpoint
+    }
+
+    fn take_checkpoint(&mut self, timestamp: Timestamp) {
+        let checkpoint = self.fold_history(timestamp);
+        self.checkpoints.insert(timestamp, checkpoint);
+        self.commands_since_checkpoint = 0;
+    }
+
+    /// Rebuild the rolling snapshot from the base and the history, keeping the
+    /// entities the history refers to
+    pub fn rebuild_rolling_snapshot(&mut self) {
+        let state = match self.command_history.keys().next_back() {
+            Some(&latest) => self.fold_history(latest),
+            None => self.base.clone(),
+        };
+        self.rolling_snapshot = Snapshot::new();
+        state.restore_in_place(&mut self.rolling_snapshot.world);
+        self.namespaces.clear();
+    }
+
+    /// Recompute the checkpoints of the whole history, after it has been loaded
+    pub fn rebuild_checkpoints(&mut self) {
+        self.checkpoints.clear();
+        self.commands_since_checkpoint = 0;
+        self.command_count = self.command_history.values().map(Vec::len).sum();
+        self.index = HistoryIndex::default();
+
+        let mut checkpoint = self.base.clone();
+        for (&timestamp, commands) in self.command_history.iter() {
+            for command in commands {
+                checkpoint.apply(command);
+                self.index.insert(timestamp, command);
+            }
+            self.commands_since_checkpoint += commands.len();
+            if self.commands_since_checkpoint >= CHECKPOINT_INTERVAL {
+                self.checkpoints.insert(timestamp, checkpoint.clone());
+                self.commands_since_checkpoint = 0;
+            }
+        }
+    }
+
     fn extract_layout_by_remote_id(&self) -> HashMap<Entity, LayoutData> {
         let mut layout_data = HashMap::new();
         let current_world = self.current_world();
-        let current_snapshot = match self.current_view_mode {
-            ViewMode::Rolling => &self.rolling_snapshot,
-            ViewMode::Specific(_) => &self.fixed_snapshot,
-        };
 
-        // Create reverse mapping: local_id -> remote_id
-        let local_to_remote: HashMap<Entity, Entity> = current_snapshot
-            .remote_entities
-            .iter()
-            .map(|(remote, local)| (*local, *remote))
-            .collect();
+        // Create reverse mapping: local_id -> remote_id. The remote ids of the
+        // fixed snapshot are the entities of the rolling one.
+        let local_to_remote: HashMap<Entity, Entity> = match self.current_view_mode {
+            ViewMode::Rolling => current_world
+                .iter()
+                .map(|entity| (entity.entity(), entity.entity()))
+                .collect(),
+            ViewMode::Specific(_) => self
+                .fixed_snapshot
+                .remote_entities
+                .iter()
+                .map(|(remote, local)| (local, remote))
+                .collect(),
+        };
 
         // Extract layout keyed by remote entity ID
         for (local_entity, _) in current_world.query::<&Node>().iter() {
@@ -225,7 +569,7 @@ impl DataStore {
 
         for (remote_entity, layout) in layout_data {
             // Look up new local entity ID
-            if let Some(&local_entity) = self.fixed_snapshot.remote_entities.get(&remote_entity) {
+            if let Some(local_entity) = self.fixed_snapshot.remote_entities.local(remote_entity) {
                 if let Some(pos) = layout.position {
                     buffer.insert_one(local_entity, pos);
                 }
@@ -245,6 +589,130 @@ impl DataStore {
             .collect()
     }
 
+    /// Property changes of an entity of the current world in a range of the
+    /// history, in the order they were recorded
+    pub fn property_changes<R>(&self, entity: Entity, range: R) -> Vec<(Timestamp, &PropertyChange)>
+    where
+        R: std::ops::RangeBounds<Timestamp>,
+    {
+        let Some(entity) = self.history_entity(entity) else {
+            return Vec::new();
+        };
+        let Some(times) = self.index.property_changes.get(&entity) else {
+            return Vec::new();
+        };
+
+        times
+            .range(range)
+            .filter_map(|ts| Some((*ts, self.command_history.get(ts)?)))
+            .flat_map(|(ts, commands)| {
+                commands.iter().filter_map(move |command| match command {
+                    Command::SpawnOrInsert(e, SpawnOrInsert::PropertyChange(change))
+                        if *e == entity =>
+                    {
+                        Some((ts, change))
+                    }
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    /// Bus messages of all the entities in a range of the history, with the
+    /// entity of the current world that posted them if it still exists
+    pub fn bus_messages<R>(&self, range: R) -> Vec<(Timestamp, Option<Entity>, &BusMessage)>
+    where
+        R: std::ops::RangeBounds<Timestamp>,
+    {
+        self.index
+            .messages
+            .range(range)
+            .filter_map(|ts| Some((*ts, self.command_history.get(ts)?)))
+            .flat_map(|(ts, commands)| {
+                commands.iter().filter_map(move |command| match command {
+                    Command::SpawnOrInsert(e, SpawnOrInsert::BusMessage(message)) => {
+                        Some((ts, self.world_entity(*e), message))
+                    }
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    /// Stalls flagged by the watchdog in a range of the history, with the
+    /// entity of the current world they were flagged on if it still exists
+    pub fn stalls<R>(&self, range: R) -> Vec<(Timestamp, Option<Entity>, &Stalled)>
+    where
+        R: std::ops::RangeBounds<Timestamp>,
+    {
+        self.index
+            .stalls
+            .range(range)
+            .filter_map(|ts| Some((*ts, self.command_history.get(ts)?)))
+            .flat_map(|(ts, commands)| {
+                commands.iter().filter_map(move |command| match command {
+                    Command::SpawnOrInsert(e, SpawnOrInsert::Stalled(stalled)) => {
+                        Some((ts, self.world_entity(*e), stalled))
+                    }
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    /// Entity of the history corresponding to one of the current world
+    fn history_entity(&self, entity: Entity) -> Option<Entity> {
+        match self.current_view_mode {
+            ViewMode::Rolling => Some(entity),
+            ViewMode::Specific(_) => self.fixed_snapshot.remote_entities.remote(entity),
+        }
+    }
+
+    /// Entity of the current world corresponding to one of the history
+    fn world_entity(&self, entity: Entity) -> Option<Entity> {
+        match self.current_view_mode {
+            ViewMode::Rolling => self
+                .rolling_snapshot
+                .world
+                .contains(entity)
+                .then_some(entity),
+            ViewMode::Specific(_) => self.fixed_snapshot.remote_entities.local(entity),
+        }
+    }
+
+    /// Session and id on the sender side of an entity of the current world,
+    /// as long as its session is connected
+    fn remote_entity(&self, entity: Entity) -> Option<(SessionId, Entity)> {
+        let entity = self.history_entity(entity)?;
+        self.namespaces
+            .iter()
+            .filter(|(&session, _)| session != SessionId::default())
+            .find_map(|(&session, namespace)| {
+                Some((session, namespace.remote_entities.remote(entity)?))
+            })
+    }
+
+    /// Whether the process that sent an entity is still connected
+    pub fn is_live(&self, entity: Entity) -> bool {
+        self.remote_entity(entity).is_some()
+    }
+
+    /// Queue a control for the process that sent an entity, nothing is sent
+    /// once it is disconnected
+    pub fn control(&mut self, entity: Entity, control: Control) {
+        match self.remote_entity(entity) {
+            Some((session, entity)) => self
+                .controls
+                .push((session, ControlRequest { entity, control })),
+            None => debug!("Ignoring control of {entity:?}, its session is not connected"),
+        }
+    }
+
+    /// Controls queued since the previous call, with the session to send them to
+    pub fn take_controls(&mut self) -> Vec<(SessionId, ControlRequest)> {
+        std::mem::take(&mut self.controls)
+    }
+
     pub fn timestamp_bounds(&self) -> Option<std::ops::RangeInclusive<Timestamp>> {
         if self.command_history.is_empty() {
             None
@@ -256,10 +724,7 @@ impl DataStore {
     }
 
     pub fn history_len(&self) -> usize {
-        self.command_history
-            .values()
-            .map(|commands| commands.len())
-            .sum()
+        self.command_count
     }
 }
 
@@ -271,7 +736,7 @@ struct LayoutData {
 // FIXME the extra enums are fairly verbose and brainfuck, find a cleaner
 // way of doing all this
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Command {
     SpawnOrInsert(Entity, SpawnOrInsert),
     Remove(Entity, Remove),
@@ -279,23 +744,40 @@ pub enum Command {
 }
 
 impl Command {
-    pub fn translate_entities(
-        &mut self,
-        mapping: &mut HashMap<Entity, Entity>,
-        world: &mut hecs::World,
-    ) {
+    pub fn translate_entities(&mut self, mapping: &mut EntityMap, world: &mut hecs::World) {
         let entity = match self {
             Command::SpawnOrInsert(entity, component) => {
                 component.translate_entities(mapping, world);
                 entity
             }
             Command::Remove(entity, _) => entity,
-            Command::Despawn(entity) => entity,
+            Command::Despawn(entity) => {
+                // Forget the entity so that a reused remote id maps to a new one
+                *entity = mapping.remove(*entity).unwrap_or(Entity::DANGLING);
+                return;
+            }
         };
 
-        *entity = *mapping
-            .entry(*entity)
-            .or_insert_with(|| world.reserve_entity());
+        *entity = mapping.map(*entity, world);
+    }
+
+    /// Whether running the command would leave the world unchanged
+    pub fn is_noop(&self, world: &hecs::World) -> bool {
+        match self {
+            Command::SpawnOrInsert(entity, component) => component.is_noop(world, *entity),
+            Command::Remove(entity, component) => component.is_noop(world, *entity),
+            Command::Despawn(_) => false,
+        }
+    }
+
+    /// Whether the command is only of interest while it is live, such
+    /// commands are applied to the rolling snapshot without being recorded
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Command::SpawnOrInsert(_, SpawnOrInsert::Preview(_))
+                | Command::Remove(_, Remove::Preview)
+        )
     }
 
     pub fn run_on(self, world: &mut hecs::World) {
@@ -305,19 +787,22 @@ impl Command {
             }
             Command::Remove(entity, component) => component.append_to(world, entity),
             Command::Despawn(entity) => {
-                world.despawn(entity).unwrap();
+                if let Err(e) = world.despawn(entity) {
+                    debug!("Failed to despawn {entity:?}: {e}");
+                }
             }
         }
     }
 }
 
 #[enum_dispatch]
-pub trait AppendTo: Sized + hecs::Component {
-    fn translate_entities(
-        &mut self,
-        _mapping: &mut HashMap<Entity, Entity>,
-        _world: &mut hecs::World,
-    ) {
+pub trait AppendTo: Sized + hecs::Component + PartialEq {
+    fn translate_entities(&mut self, _mapping: &mut EntityMap, _world: &mut hecs::World) {}
+
+    fn is_noop(&self, world: &hecs::World, entity: Entity) -> bool {
+        world
+            .get::<&Self>(entity)
+            .is_ok_and(|component| *component == *self)
     }
 
     fn append_to(self, world: &mut hecs::World, entity: Entity) {
@@ -325,94 +810,86 @@ pub trait AppendTo: Sized + hecs::Component {
     }
 }
 
-impl AppendTo for Child {
-    fn translate_entities(
-        &mut self,
-        mapping: &mut HashMap<Entity, Entity>,
-        world: &mut hecs::World,
-    ) {
-        self.parent = *mapping
-            .entry(self.parent)
-            .or_insert_with(|| world.reserve_entity())
-    }
+/// A component that can be sent to the app, see `components!`
+pub trait Component: AppendTo + Into<SpawnOrInsert> {
+    const KIND: Remove;
 }
 
-impl AppendTo for Edge {
-    fn translate_entities(
-        &mut self,
-        mapping: &mut HashMap<Entity, Entity>,
-        world: &mut hecs::World,
-    ) {
-        self.output_port = *mapping
-            .entry(self.output_port)
-            .or_insert_with(|| world.reserve_entity());
-
-        self.input_port = *mapping
-            .entry(self.input_port)
-            .or_insert_with(|| world.reserve_entity());
-    }
-}
-
-impl AppendTo for Name {}
-impl AppendTo for Node {}
-impl AppendTo for Port {}
-impl AppendTo for Properties {}
-impl AppendTo for State {}
-impl AppendTo for TypeName {}
-
-#[enum_dispatch(AppendTo)]
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum SpawnOrInsert {
-    Node(Node),
-    Edge(Edge),
-    State(State),
-    Name(Name),
-    TypeName(TypeName),
-    Properties(Properties),
-    Port(Port),
-    Child(Child),
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Remove {
+components! {
     Node,
-    Edge,
+    Edge { output_port, input_port },
     State,
     Name,
     TypeName,
     Properties,
     Port,
-    Child,
+    Child { parent },
+    PadStats,
+    Caps,
+    Session,
+    Label,
+    Kind,
+    Attributes,
+    #[custom] PropertyChange,
+    DotGraph,
+    QueriedLatency,
+    MessageLog,
+    #[custom] BusMessage,
+    ProcessingLatency,
+    EndToEndLatency,
+    Preview,
+    QueueLevel,
+    ThreadInfo,
+    Thread { thread },
+    CpuUsage,
+    Stalled,
 }
 
 impl AppendTo for Remove {
+    fn is_noop(&self, world: &hecs::World, entity: Entity) -> bool {
+        !self.is_in(world, entity)
+    }
+
     fn append_to(self, world: &mut hecs::World, entity: Entity) {
-        match self {
-            Remove::Node => {
-                world.remove_one::<Node>(entity).unwrap();
-            }
-            Remove::Edge => {
-                world.remove_one::<Edge>(entity).unwrap();
-            }
-            Remove::State => {
-                world.remove_one::<State>(entity).unwrap();
-            }
-            Remove::Name => {
-                world.remove_one::<Name>(entity).unwrap();
-            }
-            Remove::TypeName => {
-                world.remove_one::<TypeName>(entity).unwrap();
-            }
-            Remove::Properties => {
-                world.remove_one::<Properties>(entity).unwrap();
-            }
-            Remove::Port => {
-                world.remove_one::<Port>(entity).unwrap();
-            }
-            Remove::Child => {
-                world.remove_one::<Child>(entity).unwrap();
-            }
+        self.remove_from(world, entity);
+    }
+}
+
+impl AppendTo for PropertyChange {
+    fn is_noop(&self, world: &hecs::World, entity: Entity) -> bool {
+        world.get::<&Properties>(entity).is_ok_and(|properties| {
+            properties
+                .0
+                .get(&self.name)
+                .is_some_and(|property| property.value == self.value)
+        })
+    }
+
+    fn append_to(self, world: &mut hecs::World, entity: Entity) {
+        if let Ok(mut properties) = world.get::<&mut Properties>(entity) {
+            properties.apply(self);
+            return;
+        }
+        let mut properties = Properties::default();
+        properties.apply(self);
+        world.insert_one(entity, properties).unwrap();
+    }
+}
+
+impl AppendTo for BusMessage {
+    // Every message is an event of its own
+    fn is_noop(&self, _world: &hecs::World, _entity: Entity) -> bool {
+        false
+    }
+
+    fn append_to(self, world: &mut hecs::World, entity: Entity) {
+        if let Ok(mut log) = world.get::<&mut MessageLog>(entity) {
+            log.push(self);
+            return;
         }
+        let mut log = MessageLog::default();
+        log.push(self);
+        world.insert_one(entity, log).unwrap();
     }
 }
 
@@ -430,10 +907,10 @@ mod tests {
         let entity2 = temp_world.spawn(());
 
         let cmd1 = Command::SpawnOrInsert(entity1, SpawnOrInsert::Node(Node {}));
-        datastore.record_command(cmd1);
+        datastore.record_command(0, cmd1);
 
         let cmd2 = Command::SpawnOrInsert(entity2, SpawnOrInsert::Node(Node {}));
-        datastore.record_command(cmd2);
+        datastore.record_command(1, cmd2);
 
         // Should start in rolling mode
         assert!(matches!(datastore.current_view_mode, ViewMode::Rolling));
@@ -451,13 +928,13 @@ mod tests {
         let entity3 = temp_world.spawn(());
 
         let cmd1 = Command::SpawnOrInsert(entity1, SpawnOrInsert::Node(Node {}));
-        datastore.record_command(cmd1);
+        datastore.record_command(0, cmd1);
 
         let cmd2 = Command::SpawnOrInsert(entity2, SpawnOrInsert::Node(Node {}));
-        datastore.record_command(cmd2);
+        datastore.record_command(1, cmd2);
 
         let cmd3 = Command::SpawnOrInsert(entity3, SpawnOrInsert::Node(Node {}));
-        datastore.record_command(cmd3);
+        datastore.record_command(2, cmd3);
 
         // Rolling snapshot should have 3 entities
         assert_eq!(datastore.rolling_snapshot.world.len(), 3);
@@ -488,7 +965,7 @@ mod tests {
         let entity2 = temp_world.spawn(());
 
         let cmd1 = Command::SpawnOrInsert(entity1, SpawnOrInsert::Node(Node {}));
-        datastore.record_command(cmd1);
+        datastore.record_command(0, cmd1);
 
         // Default should be rolling
         assert!(matches!(datastore.current_view_mode, ViewMode::Rolling));
@@ -502,7 +979,7 @@ mod tests {
 
         // Add another command (should only affect rolling)
         let cmd2 = Command::SpawnOrInsert(entity2, SpawnOrInsert::Node(Node {}));
-        datastore.record_command(cmd2);
+        datastore.record_command(1, cmd2);
 
         // Fixed view should still have 1 entity
         assert_eq!(datastore.current_world().len(), 1);
@@ -524,13 +1001,13 @@ mod tests {
 
         // Add three commands
         let cmd1 = Command::SpawnOrInsert(entity1, SpawnOrInsert::Node(Node {}));
-        datastore.record_command(cmd1);
+        datastore.record_command(0, cmd1);
 
         let cmd2 = Command::SpawnOrInsert(entity2, SpawnOrInsert::Node(Node {}));
-        datastore.record_command(cmd2);
+        datastore.record_command(1, cmd2);
 
         let cmd3 = Command::SpawnOrInsert(entity3, SpawnOrInsert::Node(Node {}));
-        datastore.record_command(cmd3);
+        datastore.record_command(2, cmd3);
 
         // Should start in rolling mode at latest timestamp
         assert!(matches!(datastore.current_view_mode, ViewMode::Rolling));
@@ -581,6 +1058,520 @@ mod tests {
         assert_eq!(datastore.current_timeline_position(), Some(2));
     }
 
+    #[test]
+    fn test_despawn() {
+        let mut datastore = DataStore::default();
+
+        let mut temp_world = hecs::World::new();
+        let parent = temp_world.spawn(());
+        let child = temp_world.spawn(());
+
+        datastore.record_command(0, Command::SpawnOrInsert(parent, Node.into()));
+        datastore.record_command(1, Command::SpawnOrInsert(child, Node.into()));
+        datastore.record_command(2, Command::SpawnOrInsert(child, Child { parent }.into()));
+        datastore.record_command(3, Command::Remove(child, Remove::Child));
+        datastore.record_command(4, Command::Despawn(child));
+        assert_eq!(datastore.rolling_snapshot.world.len(), 1);
+
+        // Despawning an entity that was never seen is not recorded
+        datastore.record_command(4, Command::Despawn(child));
+        assert_eq!(datastore.history_len(), 5);
+
+        // A reused remote id gets a new entity
+        datastore.record_command(5, Command::SpawnOrInsert(child, Node.into()));
+        assert_eq!(datastore.rolling_snapshot.world.len(), 2);
+
+        // Replaying the history gives the same world
+        datastore.set_view(ViewMode::Specific(4));
+        assert_eq!(datastore.current_world().len(), 1);
+        datastore.set_view(ViewMode::Specific(5));
+        assert_eq!(datastore.current_world().len(), 2);
+        assert_eq!(
+            datastore.current_world().query::<&Child>().iter().count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let mut datastore = DataStore::default();
+
+        let mut temp_world = hecs::World::new();
+        let entity = temp_world.spawn(());
+
+        let command = |ts| TimedCommand {
+            ts,
+            wall_clock: 1_000_000_000_000 + ts,
+            command: Command::SpawnOrInsert(entity, Name(format!("{ts}")).into()),
+        };
+
+        datastore.record(SessionId(1), command(2_000_000_000));
+        datastore.record(SessionId(1), command(2_300_000_000));
+        assert_eq!(datastore.wall_clock_origin, Some(1_000_000_000_000));
+
+        // A late command is recorded at the latest timestamp
+        datastore.record(SessionId(1), command(2_200_000_000));
+        assert_eq!(
+            datastore.timestamp_bounds(),
+            Some(2_000_000_000..=2_300_000_000)
+        );
+        assert_eq!(datastore.command_history[&2_300_000_000].len(), 2);
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        // Describe the fixed world by remote ids, as local ids depend on the
+        // order entities were created in
+        fn describe(datastore: &DataStore) -> BTreeMap<u64, (Option<Name>, Option<u64>)> {
+            let snapshot = &datastore.fixed_snapshot;
+            let remote: HashMap<Entity, u64> = snapshot
+                .remote_entities
+                .iter()
+                .map(|(remote, local)| (local, remote.to_bits().get()))
+                .collect();
+            snapshot
+                .world
+                .query::<&Node>()
+                .iter()
+                .map(|(entity, _)| {
+                    let name = snapshot
+                        .world
+                        .get::<&Name>(entity)
+                        .ok()
+                        .map(|n| (*n).clone());
+                    // Parents that were despawned are either dangling or
+                    // reserved again, both are equally absent
+                    let parent = snapshot
+                        .world
+                        .parent(entity)
+                        .filter(|&p| snapshot.world.satisfies::<&Node>(p).unwrap_or(false))
+                        .map(|p| remote[&p]);
+                    (remote[&entity], (name, parent))
+                })
+                .collect()
+        }
+
+        let mut datastore = DataStore::default();
+        let mut temp_world = hecs::World::new();
+        let entities: Vec<Entity> = (0..100).map(|_| temp_world.spawn(())).collect();
+
+        let mut ts = 0;
+        for round in 0..300 {
+            for (i, &entity) in entities.iter().enumerate() {
+                ts += 1;
+                let command = match (round + i) % 5 {
+                    0 => Command::SpawnOrInsert(entity, Node.into()),
+                    1 => Command::SpawnOrInsert(entity, Name(format!("{round}")).into()),
+                    2 => Command::SpawnOrInsert(
+                        entity,
+                        Child {
+                            parent: entities[(i + round) % entities.len()],
+                        }
+                        .into(),
+                    ),
+                    3 => Command::Remove(entity, Remove::Name),
+                    _ => Command::Despawn(entity),
+                };
+                datastore.record_command(ts, command);
+            }
+        }
+        assert!(datastore.checkpoints.len() > 1);
+
+        for position in [5_000, CHECKPOINT_INTERVAL as u64, 15_001, 25_000, ts] {
+            datastore.set_view(ViewMode::Specific(position));
+            let from_checkpoint = describe(&datastore);
+
+            let checkpoints = std::mem::take(&mut datastore.checkpoints);
+            datastore.set_view(ViewMode::Specific(position));
+            let from_zero = describe(&datastore);
+            datastore.checkpoints = checkpoints;
+
+            assert!(!from_zero.is_empty());
+            assert_eq!(from_checkpoint, from_zero);
+        }
+
+        // Rebuilt checkpoints are equivalent to the ones taken while recording
+        let checkpoints = datastore.checkpoints.clone();
+        datastore.rebuild_checkpoints();
+        assert_eq!(datastore.checkpoints, checkpoints);
+    }
+
+    #[test]
+    fn test_deduplication() {
+        let mut datastore = DataStore::default();
+        let mut temp_world = hecs::World::new();
+        let entity = temp_world.spawn(());
+
+        datastore.record_command(0, Command::SpawnOrInsert(entity, Name("a".into()).into()));
+        datastore.record_command(1, Command::SpawnOrInsert(entity, Name("a".into()).into()));
+        assert_eq!(datastore.history_len(), 1);
+
+        datastore.record_command(2, Command::SpawnOrInsert(entity, Name("b".into()).into()));
+        datastore.record_command(3, Command::Remove(entity, Remove::Node));
+        assert_eq!(datastore.history_len(), 2);
+    }
+
+    #[test]
+    fn test_retention() {
+        let mut datastore = DataStore::default();
+        datastore.retention = RetentionPolicy {
+            max_commands: Some(10),
+            max_age: Some(100),
+        };
+
+        let mut temp_world = hecs::World::new();
+        let entities: Vec<Entity> = (0..4).map(|_| temp_world.spawn(())).collect();
+
+        for ts in 0..50 {
+            let entity = entities[ts as usize % entities.len()];
+            datastore.record_command(
+                ts,
+                Command::SpawnOrInsert(entity, Name(format!("{ts}")).into()),
+            );
+        }
+        assert_eq!(datastore.history_len(), 10);
+        assert_eq!(datastore.timestamp_bounds(), Some(40..=49));
+
+        // The oldest view still has the folded commands
+        datastore.set_view(ViewMode::Specific(40));
+        assert_eq!(datastore.fixed_snapshot.world.len(), 4);
+
+        // Commands older than the maximum age are folded as well
+        datastore.record_command(200, Command::Despawn(entities[0]));
+        assert_eq!(datastore.timestamp_bounds(), Some(200..=200));
+        datastore.set_view(ViewMode::Specific(200));
+        assert_eq!(datastore.fixed_snapshot.world.len(), 3);
+    }
+
+    #[test]
+    fn test_sessions() {
+        let mut datastore = DataStore::default();
+        let mut temp_world = hecs::World::new();
+        let entity = temp_world.spawn(());
+
+        let command = |ts, wall_clock, name: &str| TimedCommand {
+            ts,
+            wall_clock,
+            command: Command::SpawnOrInsert(entity, Name(name.into()).into()),
+        };
+
+        // Both sessions use the same remote id, 1s apart
+        datastore.record(SessionId(1), command(100, 10_000_000_100, "sender"));
+        datastore.record(SessionId(2), command(50, 11_000_000_050, "receiver"));
+        assert_eq!(datastore.rolling_snapshot.world.len(), 2);
+        assert_eq!(datastore.timestamp_bounds(), Some(100..=1_000_000_050));
+
+        let names: std::collections::HashSet<String> = datastore
+            .rolling_snapshot
+            .world
+            .query::<&Name>()
+            .iter()
+            .map(|(_, name)| name.0.clone())
+            .collect();
+        assert_eq!(names, ["sender".into(), "receiver".into()].into());
+
+        // A despawn only applies to the namespace of its session
+        datastore.record(
+            SessionId(2),
+            TimedCommand {
+                ts: 60,
+                wall_clock: 11_000_000_060,
+                command: Command::Despawn(entity),
+            },
+        );
+        datastore.end_session(SessionId(2));
+        assert_eq!(datastore.rolling_snapshot.world.len(), 1);
+    }
+
+    #[test]
+    fn test_controls() {
+        let mut datastore = DataStore::default();
+        let mut temp_world = hecs::World::new();
+        let remote = temp_world.spawn(());
+
+        datastore.record(
+            SessionId(1),
+            TimedCommand {
+                ts: 0,
+                wall_clock: 0,
+                command: Command::SpawnOrInsert(remote, Node.into()),
+            },
+        );
+        let (entity, _) = datastore
+            .rolling_snapshot
+            .world
+            .query::<&Node>()
+            .iter()
+            .next()
+            .unwrap();
+        assert!(datastore.is_live(entity));
+
+        // Controls are addressed to the entity of the session
+        datastore.control(entity, Control::SetState(State::Paused));
+        assert_eq!(
+            datastore.take_controls(),
+            vec![(
+                SessionId(1),
+                ControlRequest {
+                    entity: remote,
+                    control: Control::SetState(State::Paused),
+                }
+            )]
+        );
+        assert!(datastore.take_controls().is_empty());
+
+        datastore.end_session(SessionId(1));
+        assert!(!datastore.is_live(entity));
+        datastore.control(entity, Control::SendEos);
+        assert!(datastore.take_controls().is_empty());
+    }
+
+    #[test]
+    fn test_component_registry() {
+        let mut world = hecs::World::new();
+        let parent = world.spawn((Node,));
+        let child = world.spawn((Node,));
+
+        let component: SpawnOrInsert = Child { parent }.into();
+        assert_eq!(component.kind(), Child::KIND);
+        Command::SpawnOrInsert(child, component).run_on(&mut world);
+        assert!(Remove::Child.is_in(&world, child));
+
+        Command::Remove(child, Child::KIND).run_on(&mut world);
+        assert!(!Remove::Child.is_in(&world, child));
+        assert!(Remove::Node.is_in(&world, child));
+    }
+
+    #[test]
+    fn test_property_changes() {
+        let mut datastore = DataStore::default();
+        let mut temp_world = hecs::World::new();
+        let remote = temp_world.spawn(());
+
+        let spec = ParamSpec {
+            type_name: "gdouble".into(),
+            ..Default::default()
+        };
+        let volume = Property {
+            value: PropertyValue::Float(1.0),
+            spec: spec.clone(),
+        };
+        let properties = Properties([("volume".to_string(), volume)].into());
+        datastore.record_command(0, Command::SpawnOrInsert(remote, properties.into()));
+
+        let change = |value| {
+            let change = PropertyChange {
+                name: "volume".into(),
+                value: PropertyValue::Float(value),
+            };
+            Command::SpawnOrInsert(remote, change.into())
+        };
+        datastore.record_command(10, change(0.5));
+        datastore.record_command(20, change(0.5));
+        datastore.record_command(30, change(0.2));
+        assert_eq!(datastore.history_len(), 3);
+
+        // Changes are merged into the properties, which keep their description
+        let volume = |world: &hecs::World| {
+            world
+                .query::<&Properties>()
+                .iter()
+                .map(|(entity, properties)| (entity, properties.0["volume"].clone()))
+                .next()
+                .unwrap()
+        };
+        let (entity, property) = volume(datastore.current_world());
+        assert_eq!(property.value, PropertyValue::Float(0.2));
+        assert_eq!(property.spec, spec);
+        let changes = datastore.property_changes(entity, ..);
+        let timestamps: Vec<Timestamp> = changes.iter().map(|(ts, _)| *ts).collect();
+        assert_eq!(timestamps, vec![10, 30]);
+
+        datastore.set_view(ViewMode::Specific(20));
+        let (entity, property) = volume(datastore.current_world());
+        assert_eq!(property.value, PropertyValue::Float(0.5));
+        let changes = datastore.property_changes(entity, ..=20);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].1.value, PropertyValue::Float(0.5));
+
+        // Checkpoints fold the changes the same way
+        let mut world = hecs::World::new();
+        datastore.fold_history(30).restore_in_place(&mut world);
+        assert_eq!(volume(&world).1.value, PropertyValue::Float(0.2));
+
+        // Changes of the folded commands are left out
+        datastore.set_view(ViewMode::Rolling);
+        datastore.retention.max_age = Some(15);
+        datastore.record_command(31, change(0.9));
+        let (entity, _) = volume(datastore.current_world());
+        let timestamps = |datastore: &DataStore| -> Vec<Timestamp> {
+            datastore
+                .property_changes(entity, ..)
+                .iter()
+                .map(|(ts, _)| *ts)
+                .collect()
+        };
+        assert_eq!(timestamps(&datastore), vec![30, 31]);
+        datastore.rebuild_checkpoints();
+        assert_eq!(timestamps(&datastore), vec![30, 31]);
+    }
+
+    #[test]
+    fn test_bus_messages() {
+        let mut datastore = DataStore::default();
+        let mut temp_world = hecs::World::new();
+        let remote = temp_world.spawn(());
+
+        let message = |kind, text: &str| {
+            let message = BusMessage {
+                kind,
+                source: "pipeline0/src".into(),
+                text: text.into(),
+                details: None,
+            };
+            Command::SpawnOrInsert(remote, message.into())
+        };
+        datastore.record_command(0, Command::SpawnOrInsert(remote, Node.into()));
+        datastore.record_command(10, message(MessageKind::Warning, "late"));
+        datastore.record_command(10, message(MessageKind::Warning, "late"));
+        datastore.record_command(20, message(MessageKind::Qos, "dropped"));
+        datastore.record_command(30, message(MessageKind::Error, "not negotiated"));
+        assert_eq!(datastore.history_len(), 5);
+
+        // Only errors and warnings are counted
+        let log = |world: &hecs::World| {
+            world
+                .query::<&MessageLog>()
+                .iter()
+                .map(|(_, log)| log.clone())
+                .next()
+                .unwrap()
+        };
+        let current = log(datastore.current_world());
+        assert_eq!((current.errors, current.warnings), (1, 2));
+        assert_eq!(current.last_issue.as_ref().unwrap().text, "not negotiated");
+
+        datastore.set_view(ViewMode::Specific(20));
+        assert_eq!(log(datastore.current_world()).errors, 0);
+        let messages = datastore.bus_messages(..=20);
+        let (entity, _) = datastore
+            .current_world()
+            .query::<&Node>()
+            .iter()
+            .next()
+            .unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(messages.iter().all(|(_, e, _)| *e == Some(entity)));
+        assert_eq!(messages[2].2.kind, MessageKind::Qos);
+
+        // Checkpoints count the messages the same way
+        let mut world = hecs::World::new();
+        datastore.fold_history(30).restore_in_place(&mut world);
+        assert_eq!(log(&world), current);
+
+        // Messages of the folded commands are left out
+        datastore.retention.max_age = Some(15);
+        datastore.record_command(31, message(MessageKind::Info, "done"));
+        let texts = |datastore: &DataStore| -> Vec<String> {
+            datastore
+                .bus_messages(..)
+                .iter()
+                .map(|(_, _, message)| message.text.clone())
+                .collect()
+        };
+        assert_eq!(texts(&datastore), ["dropped", "not negotiated", "done"]);
+        datastore.rebuild_checkpoints();
+        assert_eq!(texts(&datastore), ["dropped", "not negotiated", "done"]);
+    }
+
+    #[test]
+    fn test_stalls() {
+        let mut datastore = DataStore::default();
+        let mut temp_world = hecs::World::new();
+        let remote = temp_world.spawn(());
+
+        let stalled = Stalled {
+            reason: StallReason::NoBuffers,
+            since: 5,
+        };
+        datastore.record_command(0, Command::SpawnOrInsert(remote, Port::Output.into()));
+        datastore.record_command(10, Command::SpawnOrInsert(remote, stalled.into()));
+        datastore.record_command(20, Command::Remove(remote, Remove::Stalled));
+        assert!(datastore
+            .current_world()
+            .query::<&Stalled>()
+            .iter()
+            .next()
+            .is_none());
+
+        // Stalls stay in the history once recovered
+        let (pad, _) = datastore
+            .current_world()
+            .query::<&Port>()
+            .iter()
+            .next()
+            .unwrap();
+        let stalls = datastore.stalls(..);
+        assert_eq!(stalls.len(), 1);
+        assert_eq!(
+            (stalls[0].0, stalls[0].1, *stalls[0].2),
+            (10, Some(pad), stalled)
+        );
+        assert!(datastore.stalls(..10).is_empty());
+
+        datastore.set_view(ViewMode::Specific(10));
+        let (entity, current) = datastore
+            .current_world()
+            .query::<&Stalled>()
+            .iter()
+            .map(|(e, s)| (e, *s))
+            .next()
+            .unwrap();
+        assert_eq!(current, stalled);
+        assert_eq!(datastore.stalls(..=10)[0].1, Some(entity));
+
+        // Stalls of the folded commands are left out
+        datastore.retention.max_age = Some(15);
+        datastore.record_command(30, Command::SpawnOrInsert(remote, stalled.into()));
+        let times = |datastore: &DataStore| -> Vec<Timestamp> {
+            datastore.stalls(..).iter().map(|(ts, _, _)| *ts).collect()
+        };
+        assert_eq!(times(&datastore), [30]);
+        datastore.rebuild_checkpoints();
+        assert_eq!(times(&datastore), [30]);
+    }
+
+    #[test]
+    fn test_transient_commands() {
+        let mut datastore = DataStore::default();
+        let mut temp_world = hecs::World::new();
+        let remote = temp_world.spawn(());
+
+        datastore.record_command(0, Command::SpawnOrInsert(remote, Port::Output.into()));
+        let preview = Preview::Data {
+            size: 2,
+            head: vec![0xca, 0xfe],
+        };
+        datastore.record_command(10, Command::SpawnOrInsert(remote, preview.clone().into()));
+        assert_eq!(datastore.history_len(), 1);
+        assert_eq!(datastore.timestamp_bounds(), Some(0..=0));
+
+        let (pad, _) = datastore
+            .current_world()
+            .query::<&Port>()
+            .iter()
+            .next()
+            .unwrap();
+        assert_eq!(
+            *datastore.current_world().get::<&Preview>(pad).unwrap(),
+            preview
+        );
+
+        datastore.record_command(20, Command::Remove(remote, Remove::Preview));
+        assert!(!Remove::Preview.is_in(datastore.current_world(), pad));
+        assert_eq!(datastore.history_len(), 1);
+    }
+
     #[test]
     fn test_empty_datastore_timeline() {
         let mut datastore = DataStore::default();
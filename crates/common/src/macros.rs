@@ -0,0 +1,111 @@
+/// Registers the components that can be sent to the app. Each one gets a
+/// variant in `SpawnOrInsert` and `Remove`, its serialization id, and an
+/// `AppendTo` impl translating the entities listed in braces. Components
+/// marked `#[custom]` implement `AppendTo` themselves.
+///
+/// ```ignore
+/// components! {
+///     Node,
+///     Edge { output_port, input_port },
+///     #[custom] PropertyChange,
+/// }
+/// ```
+macro_rules! components {
+    ($($(#[$attr:ident])? $name:ident $({ $($field:ident),+ $(,)? })?),+ $(,)?) => {
+        #[enum_dispatch(AppendTo)]
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub enum SpawnOrInsert {
+            $($name($name),)+
+        }
+
+        impl SpawnOrInsert {
+            /// The kind of component, as used to remove it
+            pub fn kind(&self) -> Remove {
+                match self {
+                    $(SpawnOrInsert::$name(_) => Remove::$name,)+
+                }
+            }
+        }
+
+        /// Kinds of components, also used to identify them when serialized
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        pub enum Remove {
+            $($name,)+
+        }
+
+        impl Remove {
+            /// Whether the entity has a component of this kind
+            pub fn is_in(self, world: &hecs::World, entity: Entity) -> bool {
+                match self {
+                    $(Remove::$name => world.satisfies::<&$name>(entity).unwrap_or(false),)+
+                }
+            }
+
+            fn remove_from(self, world: &mut hecs::World, entity: Entity) {
+                let result = match self {
+                    $(Remove::$name => world.remove_one::<$name>(entity).map(drop),)+
+                };
+                if let Err(e) = result {
+                    debug!("Failed to remove {self:?} from {entity:?}: {e}");
+                }
+            }
+
+            pub fn serialize_components<S>(
+                entity: &hecs::EntityRef<'_>,
+                map: &mut S,
+            ) -> Result<(), S::Error>
+            where
+                S: serde::ser::SerializeMap,
+            {
+                $(hecs::serialize::row::try_serialize::<$name, _, _>(entity, &Remove::$name, map)?;)+
+                Ok(())
+            }
+
+            pub fn deserialize_component<'de, M>(
+                self,
+                map: &mut M,
+                entity: &mut hecs::EntityBuilder,
+            ) -> Result<(), M::Error>
+            where
+                M: serde::de::MapAccess<'de>,
+            {
+                match self {
+                    $(Remove::$name => {
+                        entity.add::<$name>(map.next_value()?);
+                    })+
+                }
+                Ok(())
+            }
+        }
+
+        $(
+            components!(@append_to $name $([$attr])? $({ $($field),+ })?);
+
+            impl Component for $name {
+                const KIND: Remove = Remove::$name;
+            }
+        )+
+    };
+
+    (@append_to $name:ident [custom]) => {};
+
+    (@append_to $name:ident $({ $($field:ident),+ })?) => {
+        impl AppendTo for $name {
+            components!(@translate $($($field),+)?);
+        }
+    };
+
+    (@translate) => {};
+
+    (@translate $($field:ident),+) => {
+        fn translate_entities(
+            &mut self,
+            mapping: &mut EntityMap,
+            world: &mut hecs::World,
+        ) {
+            $(
+                self.$field = mapping.map(self.$field, world);
+            )+
+        }
+    };
+}
@@ -0,0 +1,245 @@
+use crate::ser::legacy::{CheckpointV1, CommandV1};
+use crate::{checkpoint::Checkpoint, Command, DataStore, Timestamp};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Read, Write};
+use std::ops::Bound;
+use std::path::Path;
+use tracing::{info, warn};
+
+// A recording starts with the magic bytes and the version of the format,
+// followed by entries prefixed with their length as a little endian u32.
+// Entries are appended as they are recorded, so that a recording cut short
+// only loses its last entry.
+
+pub const MAGIC: &[u8; 4] = b"PWRK";
+pub const VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+enum Entry<'a> {
+    WallClockOrigin(u64),
+    Base(Cow<'a, Checkpoint>),
+    Command(Timestamp, Cow<'a, Command>),
+}
+
+/// Entries of the first version, before properties were typed
+#[derive(Deserialize)]
+enum EntryV1 {
+    WallClockOrigin(u64),
+    Base(CheckpointV1),
+    Command(Timestamp, CommandV1),
+}
+
+impl From<EntryV1> for Entry<'static> {
+    fn from(entry: EntryV1) -> Self {
+        match entry {
+            EntryV1::WallClockOrigin(origin) => Entry::WallClockOrigin(origin),
+            EntryV1::Base(base) => Entry::Base(Cow::Owned(base.into())),
+            EntryV1::Command(ts, command) => Entry::Command(ts, Cow::Owned(command.into())),
+        }
+    }
+}
+
+pub fn is_recording(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Writes the history of a datastore as it grows
+pub struct CommandWriter<W: Write> {
+    writer: W,
+    started: bool,
+    origin_written: bool,
+    // Last timestamp written and the number of its commands written so far
+    written: Option<(Timestamp, usize)>,
+}
+
+impl CommandWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::create(path).context("Failed to create file")?;
+        Self::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> CommandWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            started: false,
+            origin_written: false,
+            written: None,
+        })
+    }
+
+    /// Append what was recorded in the datastore since the previous call
+    pub fn sync(&mut self, datastore: &DataStore) -> Result<()> {
+        if !self.origin_written {
+            if let Some(origin) = datastore.wall_clock_origin {
+                self.write_entry(&Entry::WallClockOrigin(origin))?;
+                self.origin_written = true;
+            }
+        }
+
+        if !self.started {
+            if datastore.base != Checkpoint::default() {
+                self.write_entry(&Entry::Base(Cow::Borrowed(&datastore.base)))?;
+            }
+            self.started = true;
+        }
+
+        // Only the latest timestamp can have new commands appended to it
+        let start = match self.written {
+            Some((ts, _)) => Bound::Included(ts),
+            None => Bound::Unbounded,
+        };
+        for (&ts, commands) in datastore.command_history.range((start, Bound::Unbounded)) {
+            let skip = match self.written {
+                Some((written_ts, count)) if written_ts == ts => count,
+                _ => 0,
+            };
+            for command in commands.iter().skip(skip) {
+                self.write_entry(&Entry::Command(ts, Cow::Borrowed(command)))?;
+            }
+            self.written = Some((ts, commands.len()));
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush().context("Failed to flush recording")
+    }
+
+    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+        let bytes = bincode::serialize(entry).context("Failed to serialize entry")?;
+        self.writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+pub fn save_recording(datastore: &DataStore, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    info!("Saving recording to {path:?}");
+    let mut writer = CommandWriter::create(path)?;
+    writer.sync(datastore)?;
+    writer.flush()
+}
+
+pub fn read_recording(mut reader: impl Read) -> Result<DataStore> {
+    let mut header = [0u8; 8];
+    reader
+        .read_exact(&mut header)
+        .context("Failed to read header")?;
+    if !is_recording(&header) {
+        bail!("Not a recording");
+    }
+    let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+    if version > VERSION {
+        bail!("Unsupported recording version {version}");
+    }
+
+    let mut datastore = DataStore::default();
+    loop {
+        let mut length = [0u8; 4];
+        match reader.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e).context("Failed to read entry"),
+        }
+
+        let mut bytes = vec![0u8; u32::from_le_bytes(length) as usize];
+        match reader.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                warn!("Recording is truncated, ignoring its last entry");
+                break;
+            }
+            Err(e) => return Err(e).context("Failed to read entry"),
+        }
+
+        let entry = match version {
+            1 => bincode::deserialize::<EntryV1>(&bytes).map(Entry::from),
+            _ => bincode::deserialize(&bytes),
+        };
+        match entry.context("Failed to deserialize entry")? {
+            Entry::WallClockOrigin(origin) => datastore.wall_clock_origin = Some(origin),
+            Entry::Base(base) => datastore.base = base.into_owned(),
+            Entry::Command(ts, command) => {
+                datastore
+                    .command_history
+                    .entry(ts)
+                    .or_default()
+                    .push(command.into_owned());
+            }
+        }
+    }
+
+    datastore.rebuild_checkpoints();
+    datastore.rebuild_rolling_snapshot();
+    Ok(datastore)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Child, Name, Node, SessionId, TimedCommand};
+
+    fn command(ts: Timestamp, command: Command) -> TimedCommand {
+        TimedCommand {
+            ts,
+            wall_clock: 1_000 + ts,
+            command,
+        }
+    }
+
+    #[test]
+    fn test_streaming_roundtrip() {
+        let mut temp_world = hecs::World::new();
+        let parent = temp_world.spawn(());
+        let child = temp_world.spawn(());
+
+        let mut datastore = DataStore::default();
+        let mut writer = CommandWriter::new(Vec::new()).unwrap();
+
+        datastore.record(
+            SessionId(1),
+            command(10, Command::SpawnOrInsert(parent, Node.into())),
+        );
+        writer.sync(&datastore).unwrap();
+
+        // Commands appended to an already written timestamp are picked up
+        datastore.record(
+            SessionId(1),
+            command(
+                10,
+                Command::SpawnOrInsert(parent, Name("bin".into()).into()),
+            ),
+        );
+        datastore.record(
+            SessionId(1),
+            command(20, Command::SpawnOrInsert(child, Child { parent }.into())),
+        );
+        writer.sync(&datastore).unwrap();
+        writer.sync(&datastore).unwrap();
+
+        let bytes = writer.writer;
+        let loaded = read_recording(bytes.as_slice()).unwrap();
+        assert_eq!(loaded.command_history, datastore.command_history);
+        assert_eq!(loaded.wall_clock_origin, Some(1_000));
+
+        // The rolling snapshot uses the entities of the history
+        let world = &loaded.rolling_snapshot.world;
+        let (bin, _) = world.query::<&Node>().iter().next().unwrap();
+        assert_eq!(*world.get::<&Name>(bin).unwrap(), Name("bin".into()));
+        let parent = world.query::<&Child>().iter().map(|(_, c)| c.parent).next();
+        assert_eq!(parent, Some(bin));
+
+        // A truncated recording keeps its complete entries
+        let truncated = read_recording(&bytes[..bytes.len() - 3]).unwrap();
+        assert_eq!(truncated.history_len(), 2);
+    }
+}
@@ -1,9 +1,9 @@
-use crate::{comps::*, DataStore};
-use anyhow::{Context, Result};
+use crate::{checkpoint::Checkpoint, Command, DataStore, Remove, Timestamp};
+use anyhow::{bail, Context, Result};
 use hecs::serialize::row::*;
-use serde::Serialize;
-use std::{collections::HashMap, io::Read, path::Path};
-use tracing::info;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, io::Read, path::Path};
+use tracing::{info, warn};
 
 pub fn load_world(path: impl AsRef<Path>) -> Result<hecs::World> {
     let path = path.as_ref();
@@ -50,17 +50,13 @@ pub fn save_datastore(datastore: &DataStore, path: impl AsRef<Path>) -> Result<(
         .context("Failed to serialize world")?;
     }
 
-    // Create a container structure for both world and datastore data
-    #[derive(serde::Serialize)]
-    struct DataStoreContainer {
-        world_data: String,
-        command_history: std::collections::BTreeMap<crate::Timestamp, Vec<crate::Command>>,
-    }
-
     let container = DataStoreContainer {
+        version: FORMAT_VERSION,
         world_data: String::from_utf8(world_bytes)
             .context("Failed to convert world data to string")?,
         command_history: datastore.command_history.clone(),
+        wall_clock_origin: datastore.wall_clock_origin,
+        base: datastore.base.clone(),
     };
 
     container
@@ -78,47 +74,259 @@ pub fn load_datastore(path: impl AsRef<Path>) -> Result<DataStore> {
         .read_to_end(&mut bytes)
         .context("Failed to read file")?;
 
+    if crate::recording::is_recording(&bytes) {
+        return crate::recording::read_recording(bytes.as_slice());
+    }
+
     #[derive(serde::Deserialize)]
-    struct DataStoreContainer {
-        world_data: String,
-        command_history: std::collections::BTreeMap<crate::Timestamp, Vec<crate::Command>>,
-    }
-
-    let container: DataStoreContainer =
-        ron::de::from_bytes(&bytes).context("Failed to deserialize datastore")?;
-
-    // Deserialize the world from the embedded world data
-    let world_bytes = container.world_data.as_bytes();
-    let mut world_deserializer = ron::de::Deserializer::from_bytes(world_bytes)
-        .context("Failed to create world deserializer")?;
-    let world = hecs::serialize::row::deserialize(&mut SerContext, &mut world_deserializer)
-        .context("Failed to deserialize world")?;
-
-    Ok(DataStore {
-        rolling_snapshot: crate::Snapshot {
-            world,
-            remote_entities: HashMap::new(),
-        },
-        fixed_snapshot: crate::Snapshot::new(),
-        current_view_mode: crate::ViewMode::Rolling,
+    struct Version {
+        #[serde(default)]
+        version: u32,
+    }
+
+    let Version { version } =
+        ron::de::from_bytes(&bytes).context("Failed to read the format version")?;
+    let container: DataStoreContainer = match version {
+        0 => ron::de::from_bytes::<legacy::DataStoreV0>(&bytes)
+            .context("Failed to deserialize version 0 datastore")?
+            .into(),
+        1 => ron::de::from_bytes::<legacy::DataStoreV1>(&bytes)
+            .context("Failed to deserialize version 1 datastore")?
+            .into(),
+        FORMAT_VERSION => ron::de::from_bytes(&bytes).context("Failed to deserialize datastore")?,
+        v => bail!("Unsupported format version {v}, the file was saved by a newer version"),
+    };
+
+    // The world is only a cache of the history, it is rebuilt when it was
+    // saved with components that no longer exist
+    let world = ron::de::Deserializer::from_bytes(container.world_data.as_bytes())
+        .context("Failed to create world deserializer")
+        .and_then(|mut deserializer| {
+            hecs::serialize::row::deserialize(&mut SerContext, &mut deserializer)
+                .context("Failed to deserialize world")
+        });
+
+    let mut datastore = DataStore {
         command_history: container.command_history,
-    })
+        wall_clock_origin: container.wall_clock_origin,
+        base: container.base,
+        ..Default::default()
+    };
+    datastore.rebuild_checkpoints();
+    match world {
+        Ok(world) => datastore.rolling_snapshot.world = world,
+        Err(e) => {
+            warn!("{e:#}, rebuilding the world from the history");
+            datastore.rebuild_rolling_snapshot();
+        }
+    }
+
+    Ok(datastore)
 }
 
-pub struct SerContext;
+/// Version of the layout written by `save_datastore`, files without a version
+/// field predate it and are version 0
+pub const FORMAT_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct DataStoreContainer {
+    version: u32,
+    world_data: String,
+    command_history: BTreeMap<Timestamp, Vec<Command>>,
+    wall_clock_origin: Option<u64>,
+    base: Checkpoint,
+}
+
+/// Layouts of the previous versions, each one migrates to the next
+pub(crate) mod legacy {
+    use super::*;
+    use crate::{comps::*, SpawnOrInsert};
+    use hecs::Entity;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    pub struct DataStoreV0 {
+        world_data: String,
+        command_history: BTreeMap<Timestamp, Vec<CommandV1>>,
+        #[serde(default)]
+        wall_clock_origin: Option<u64>,
+        #[serde(default)]
+        base: CheckpointV1,
+    }
+
+    impl From<DataStoreV0> for DataStoreV1 {
+        fn from(v0: DataStoreV0) -> Self {
+            Self {
+                world_data: v0.world_data,
+                command_history: v0.command_history,
+                wall_clock_origin: v0.wall_clock_origin,
+                base: v0.base,
+            }
+        }
+    }
+
+    impl From<DataStoreV0> for DataStoreContainer {
+        fn from(v0: DataStoreV0) -> Self {
+            DataStoreV1::from(v0).into()
+        }
+    }
+
+    /// Properties were stored as the debug strings of their values
+    #[derive(Deserialize)]
+    pub struct DataStoreV1 {
+        world_data: String,
+        command_history: BTreeMap<Timestamp, Vec<CommandV1>>,
+        wall_clock_origin: Option<u64>,
+        base: CheckpointV1,
+    }
+
+    impl From<DataStoreV1> for DataStoreContainer {
+        fn from(v1: DataStoreV1) -> Self {
+            Self {
+                version: 2,
+                world_data: v1.world_data,
+                command_history: v1
+                    .command_history
+                    .into_iter()
+                    .map(|(ts, commands)| (ts, commands.into_iter().map(Into::into).collect()))
+                    .collect(),
+                wall_clock_origin: v1.wall_clock_origin,
+                base: v1.base.into(),
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub enum CommandV1 {
+        SpawnOrInsert(Entity, SpawnOrInsertV1),
+        Remove(Entity, Remove),
+        Despawn(Entity),
+    }
+
+    impl From<CommandV1> for Command {
+        fn from(command: CommandV1) -> Self {
+            match command {
+                CommandV1::SpawnOrInsert(entity, component) => {
+                    Command::SpawnOrInsert(entity, component.into())
+                }
+                CommandV1::Remove(entity, kind) => Command::Remove(entity, kind),
+                CommandV1::Despawn(entity) => Command::Despawn(entity),
+            }
+        }
+    }
+
+    #[derive(Deserialize, Default)]
+    pub struct CheckpointV1 {
+        entities: HashMap<Entity, HashMap<Remove, SpawnOrInsertV1>>,
+    }
+
+    impl From<CheckpointV1> for Checkpoint {
+        fn from(v1: CheckpointV1) -> Self {
+            let mut checkpoint = Checkpoint::default();
+            for (entity, components) in v1.entities {
+                for component in components.into_values() {
+                    checkpoint.apply(&Command::SpawnOrInsert(entity, component.into()));
+                }
+            }
+            checkpoint
+        }
+    }
+
+    // The variants keep their order, recordings identify them by index
+    #[derive(Deserialize)]
+    pub enum SpawnOrInsertV1 {
+        Node(Node),
+        Edge(Edge),
+        State(State),
+        Name(Name),
+        TypeName(TypeName),
+        Properties(PropertiesV1),
+        Port(Port),
+        Child(Child),
+        PadStats(PadStats),
+        Caps(Caps),
+        Session(Session),
+        Label(Label),
+        Kind(Kind),
+        Attributes(Attributes),
+    }
 
-#[derive(serde::Serialize, serde::Deserialize)]
-enum ComponentId {
-    Node,
-    State,
-    Name,
-    TypeName,
-    Properties,
-    Port,
-    Edge,
-    Child,
+    impl From<SpawnOrInsertV1> for SpawnOrInsert {
+        fn from(component: SpawnOrInsertV1) -> Self {
+            match component {
+                SpawnOrInsertV1::Node(c) => c.into(),
+                SpawnOrInsertV1::Edge(c) => c.into(),
+                SpawnOrInsertV1::State(c) => c.into(),
+                SpawnOrInsertV1::Name(c) => c.into(),
+                SpawnOrInsertV1::TypeName(c) => c.into(),
+                SpawnOrInsertV1::Properties(c) => c.into_properties().into(),
+                SpawnOrInsertV1::Port(c) => c.into(),
+                SpawnOrInsertV1::Child(c) => c.into(),
+                SpawnOrInsertV1::PadStats(c) => c.into(),
+                SpawnOrInsertV1::Caps(c) => c.into(),
+                SpawnOrInsertV1::Session(c) => c.into(),
+                SpawnOrInsertV1::Label(c) => c.into(),
+                SpawnOrInsertV1::Kind(c) => c.into(),
+                SpawnOrInsertV1::Attributes(c) => c.into(),
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct PropertiesV1(HashMap<String, String>);
+
+    impl PropertiesV1 {
+        fn into_properties(self) -> Properties {
+            self.0
+                .into_iter()
+                .map(|(name, value)| (name, parse_property(&value)))
+                .collect::<BTreeMap<_, _>>()
+                .into()
+        }
+    }
+
+    /// Parse values such as `(gint) -1`, the values of other types are kept
+    /// as they were displayed
+    pub(super) fn parse_property(debug: &str) -> Property {
+        let (type_name, contents) = debug
+            .strip_prefix('(')
+            .and_then(|s| s.split_once(") "))
+            .unwrap_or(("", debug));
+
+        let value = match type_name {
+            "gboolean" => match contents {
+                "TRUE" => Some(PropertyValue::Bool(true)),
+                "FALSE" => Some(PropertyValue::Bool(false)),
+                _ => None,
+            },
+            "gchar" | "gint" | "glong" | "gint64" => contents.parse().ok().map(PropertyValue::Int),
+            "guchar" | "guint" | "gulong" | "guint64" => {
+                contents.parse().ok().map(PropertyValue::UInt)
+            }
+            "gfloat" | "gdouble" => contents.parse().ok().map(PropertyValue::Float),
+            "gchararray" if contents == "NULL" => Some(PropertyValue::String(None)),
+            "gchararray" => contents
+                .strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .map(|s| PropertyValue::String(Some(s.to_string()))),
+            _ => None,
+        };
+
+        Property {
+            value: value.unwrap_or_else(|| PropertyValue::Other(contents.to_string())),
+            // Only writable properties were recorded
+            spec: ParamSpec {
+                type_name: type_name.to_string(),
+                readable: true,
+                writable: true,
+                ..Default::default()
+            },
+        }
+    }
 }
 
+pub struct SerContext;
+
 impl SerializeContext for SerContext {
     fn serialize_entity<S>(
         &mut self,
@@ -129,15 +337,8 @@ impl SerializeContext for SerContext {
         S: serde::ser::SerializeMap,
     {
         // Size, Position and Layers are not serialized as they are rebuilt
-        // by the application
-        try_serialize::<Node, _, _>(&entity, &ComponentId::Node, &mut map)?;
-        try_serialize::<State, _, _>(&entity, &ComponentId::State, &mut map)?;
-        try_serialize::<Name, _, _>(&entity, &ComponentId::Name, &mut map)?;
-        try_serialize::<TypeName, _, _>(&entity, &ComponentId::TypeName, &mut map)?;
-        try_serialize::<Properties, _, _>(&entity, &ComponentId::Properties, &mut map)?;
-        try_serialize::<Port, _, _>(&entity, &ComponentId::Port, &mut map)?;
-        try_serialize::<Edge, _, _>(&entity, &ComponentId::Edge, &mut map)?;
-        try_serialize::<Child, _, _>(&entity, &ComponentId::Child, &mut map)?;
+        // by the application, only the registered components are
+        Remove::serialize_components(&entity, &mut map)?;
         map.end()
     }
 }
@@ -151,33 +352,8 @@ impl DeserializeContext for SerContext {
     where
         M: serde::de::MapAccess<'de>,
     {
-        while let Some(key) = map.next_key()? {
-            match key {
-                ComponentId::Node => {
-                    entity.add::<Node>(map.next_value()?);
-                }
-                ComponentId::State => {
-                    entity.add::<State>(map.next_value()?);
-                }
-                ComponentId::Name => {
-                    entity.add::<Name>(map.next_value()?);
-                }
-                ComponentId::TypeName => {
-                    entity.add::<TypeName>(map.next_value()?);
-                }
-                ComponentId::Properties => {
-                    entity.add::<Properties>(map.next_value()?);
-                }
-                ComponentId::Port => {
-                    entity.add::<Port>(map.next_value()?);
-                }
-                ComponentId::Edge => {
-                    entity.add::<Edge>(map.next_value()?);
-                }
-                ComponentId::Child => {
-                    entity.add::<Child>(map.next_value()?);
-                }
-            }
+        while let Some(kind) = map.next_key::<Remove>()? {
+            kind.deserialize_component(&mut map, entity)?;
         }
         Ok(())
     }
@@ -186,7 +362,7 @@ impl DeserializeContext for SerContext {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Command, Node, SpawnOrInsert};
+    use crate::{comps::*, Command, SpawnOrInsert};
     use tempfile::NamedTempFile;
 
     #[test]
@@ -202,8 +378,8 @@ mod tests {
         let cmd1 = Command::SpawnOrInsert(entity1, SpawnOrInsert::Node(Node {}));
         let cmd2 = Command::SpawnOrInsert(entity2, SpawnOrInsert::Node(Node {}));
 
-        datastore.record_command(cmd1);
-        datastore.record_command(cmd2);
+        datastore.record_command(0, cmd1);
+        datastore.record_command(1, cmd2);
 
         // Save to a temporary file
         let temp_file = NamedTempFile::new().expect("Failed to create temp file");
@@ -226,6 +402,93 @@ mod tests {
         );
     }
 
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_load_v0() {
+        // Saves predating the format version, like the demo ones
+        let demo = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../demo_save_1.ron");
+        let datastore = load_datastore(demo).unwrap();
+        assert_eq!(datastore.history_len(), 1152);
+        assert_eq!(datastore.timestamp_bounds(), Some(0..=1151));
+        assert!(!datastore.rolling_snapshot.world.is_empty());
+        assert_eq!(datastore.wall_clock_origin, None);
+
+        // Properties are parsed from the debug strings they were saved as
+        let world = &datastore.rolling_snapshot.world;
+        let properties = world
+            .query::<&Properties>()
+            .iter()
+            .map(|(_, p)| p.clone())
+            .find(|p| p.0.contains_key("num-buffers"))
+            .unwrap();
+        assert!(matches!(
+            properties.0["name"].value,
+            PropertyValue::String(Some(_))
+        ));
+        let num_buffers = &properties.0["num-buffers"];
+        assert_eq!(num_buffers.value, PropertyValue::Int(-1));
+        assert_eq!(num_buffers.spec.type_name, "gint");
+
+        let datastore = load_datastore(fixture("v0.ron")).unwrap();
+        assert_eq!(datastore.history_len(), 3);
+        let names: Vec<Name> = datastore
+            .rolling_snapshot
+            .world
+            .query::<&Name>()
+            .iter()
+            .map(|(_, name)| name.clone())
+            .collect();
+        assert_eq!(names, vec![Name("pipeline0".into())]);
+    }
+
+    #[test]
+    fn test_load_v1() {
+        let mut datastore = load_datastore(fixture("v1.ron")).unwrap();
+        assert_eq!(datastore.history_len(), 2);
+        assert_eq!(datastore.wall_clock_origin, Some(1_700_000_000_000_000_000));
+        assert_ne!(datastore.base, Checkpoint::default());
+
+        // The world data uses a component that no longer exists, the world
+        // is rebuilt from the base and the history
+        let world = &datastore.rolling_snapshot.world;
+        let states: Vec<State> = world.query::<&State>().iter().map(|(_, s)| *s).collect();
+        assert_eq!(states, vec![State::Playing]);
+        assert_eq!(world.query::<&Node>().iter().count(), 2);
+
+        datastore.set_view(crate::ViewMode::Specific(1_000));
+        let world = &datastore.fixed_snapshot.world;
+        assert_eq!(world.query::<&Node>().iter().count(), 1);
+    }
+
+    #[test]
+    fn test_parse_legacy_property() {
+        let property = legacy::parse_property("(gboolean) TRUE");
+        assert_eq!(property.value, PropertyValue::Bool(true));
+        assert_eq!(property.spec.type_name, "gboolean");
+
+        let property = legacy::parse_property("(gdouble) 0.5");
+        assert_eq!(property.value, PropertyValue::Float(0.5));
+
+        let property = legacy::parse_property("(gchararray) NULL");
+        assert_eq!(property.value, PropertyValue::String(None));
+
+        let property = legacy::parse_property("(GstVideoTestSrcPattern) smpte");
+        assert_eq!(property.value, PropertyValue::Other("smpte".into()));
+        assert_eq!(property.spec.type_name, "GstVideoTestSrcPattern");
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        std::fs::write(temp_file.path(), "(version: 1000)").unwrap();
+        assert!(load_datastore(temp_file.path()).is_err());
+    }
+
     #[test]
     fn test_empty_datastore_serialization() {
         let datastore = DataStore::default();
@@ -0,0 +1,88 @@
+use crate::preview::Sampler;
+use crate::props::write_property;
+use crate::registry::REGISTRY;
+use anyhow::{bail, Context, Result};
+use gst::prelude::*;
+use log::{error, info};
+use pipewerk_common::{Control, ControlRequest, DotGraph, QueriedLatency, RecordingStream};
+
+/// Apply the controls sent by the app until the connection is closed, the
+/// results of the queries are sent back on the stream. State changes can
+/// block, so this runs on a thread of its own.
+pub fn spawn(
+    stream: RecordingStream,
+    mut controls: tokio::sync::mpsc::UnboundedReceiver<ControlRequest>,
+) {
+    std::thread::Builder::new()
+        .name("pipewerk-control".into())
+        .spawn(move || {
+            let mut sampler = Sampler::default();
+            while let Some(request) = controls.blocking_recv() {
+                if let Err(e) = apply(&stream, &mut sampler, &request) {
+                    error!("Failed to apply {request:?}: {e:#}");
+                }
+            }
+        })
+        .expect("Failed to spawn the control thread");
+}
+
+fn apply(stream: &RecordingStream, sampler: &mut Sampler, request: &ControlRequest) -> Result<()> {
+    let Some(object) = REGISTRY.lookup(request.entity) else {
+        bail!("No object with id {:?}", request.entity);
+    };
+    info!("Applying {:?} to {object:?}", request.control);
+
+    match &request.control {
+        Control::SetProperty(change) => write_property(&object, &change.name, &change.value)?,
+        Control::SetState(state) => {
+            element(&object)?.set_state((*state).into())?;
+        }
+        Control::SendEos => {
+            if !element(&object)?.send_event(gst::event::Eos::new()) {
+                bail!("EOS was not handled");
+            }
+        }
+        Control::Flush => {
+            let element = element(&object)?;
+            if !element.send_event(gst::event::FlushStart::new()) {
+                bail!("Flush was not handled");
+            }
+            if !element.send_event(gst::event::FlushStop::new(true)) {
+                bail!("Flush could not be stopped");
+            }
+        }
+        Control::DumpDot => {
+            let bin = object.downcast_ref::<gst::Bin>().context("Not a bin")?;
+            let dot = bin.debug_to_dot_data(gst::DebugGraphDetails::all());
+            stream.insert_one(request.entity, DotGraph(dot.to_string()));
+        }
+        Control::QueryLatency => {
+            let mut query = gst::query::Latency::new();
+            if !element(&object)?.query(&mut query) {
+                bail!("Latency query failed");
+            }
+            let (live, min, max) = query.result();
+            let latency = QueriedLatency {
+                live,
+                min: min.nseconds(),
+                max: max.map(|max| max.nseconds()),
+            };
+            stream.insert_one(request.entity, latency);
+        }
+        Control::SamplePad(on) => {
+            let pad = object.downcast_ref::<gst::Pad>().context("Not a pad")?;
+            if *on {
+                sampler.start(stream, request.entity, pad);
+            } else {
+                sampler.stop(stream, request.entity, pad);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn element(object: &glib::Object) -> Result<&gst::Element> {
+    object
+        .downcast_ref::<gst::Element>()
+        .context("Not an element")
+}
@@ -0,0 +1,218 @@
+use crate::registry::REGISTRY;
+use crate::threads::{parse_stat, Stat};
+use hecs::Entity;
+use pipewerk_common::CpuUsage;
+use std::collections::HashMap;
+
+/// Accounts the CPU time of the process and of its streaming threads, as the
+/// `rusage` tracer of GStreamer does. The time of a thread is split evenly
+/// between the elements it runs, that is the ones pushing on its pads and
+/// the ones receiving the buffers.
+#[derive(Default)]
+pub struct CpuMonitor {
+    // Thread and elements of each pad, by pad
+    pads: HashMap<Entity, (Entity, Vec<Entity>)>,
+    // CPU times at the previous report, in nanoseconds
+    process: Option<u64>,
+    // By thread id and start time
+    threads: HashMap<(u64, u64), u64>,
+    // Threads that are gone since the previous call to `take_exited`
+    exited: Vec<(u64, u64)>,
+    elements: HashMap<Entity, u64>,
+    last_report: Option<u64>,
+}
+
+impl CpuMonitor {
+    pub fn assign(&mut self, pad: Entity, thread: Entity, elements: Vec<Entity>) {
+        for &element in elements.iter() {
+            self.elements.entry(element).or_default();
+        }
+        self.pads.insert(pad, (thread, elements));
+    }
+
+    pub fn forget(&mut self, entity: Entity) {
+        self.pads.remove(&entity);
+        self.elements.remove(&entity);
+        for (_, elements) in self.pads.values_mut() {
+            elements.retain(|&e| e != entity);
+        }
+    }
+
+    /// Forget a thread that is gone, returns the pads it was pushing on
+    pub fn forget_thread(&mut self, thread: Entity) -> Vec<Entity> {
+        let mut pads = Vec::new();
+        self.pads.retain(|&pad, (t, _)| {
+            let retain = *t != thread;
+            if !retain {
+                pads.push(pad);
+            }
+            retain
+        });
+        pads
+    }
+
+    /// Thread ids and start times of the threads that are gone, their ids
+    /// can be reused by the system
+    pub fn take_exited(&mut self) -> Vec<(u64, u64)> {
+        std::mem::take(&mut self.exited)
+    }
+
+    /// Returns the usage of the process, of its known threads and of the
+    /// elements running on them since the previous report, given the CPU
+    /// time of the process and the stats of its threads
+    pub fn report(&mut self, ts: u64, process: u64, threads: &[Stat]) -> Vec<(Entity, CpuUsage)> {
+        let elapsed = self
+            .last_report
+            .replace(ts)
+            .map(|last| ts.saturating_sub(last));
+        let usage = |delta: u64, total: u64| CpuUsage {
+            percent: match elapsed {
+                Some(elapsed) if elapsed > 0 => delta as f64 * 100.0 / elapsed as f64,
+                _ => 0.0,
+            },
+            total,
+        };
+
+        let mut report = Vec::new();
+        let previous = self.process.replace(process).unwrap_or(process);
+        report.push((
+            REGISTRY.session(),
+            usage(process.saturating_sub(previous), process),
+        ));
+
+        // Threads that are gone are forgotten, their ids can be reused
+        self.threads.retain(|&key, _| {
+            let retain = threads.iter().any(|s| (s.id, s.start_time) == key);
+            if !retain {
+                self.exited.push(key);
+            }
+            retain
+        });
+
+        let mut element_deltas: HashMap<Entity, u64> = HashMap::new();
+        for stat in threads.iter() {
+            let total = stat.cpu_time;
+            let key = (stat.id, stat.start_time);
+            let previous = self.threads.insert(key, total).unwrap_or(total);
+            let Some(thread) = REGISTRY.find_thread(stat.id, stat.start_time) else {
+                continue;
+            };
+            let delta = total.saturating_sub(previous);
+            report.push((thread, usage(delta, total)));
+
+            let mut elements: Vec<Entity> = self
+                .pads
+                .values()
+                .filter(|(t, _)| *t == thread)
+                .flat_map(|(_, elements)| elements.iter().copied())
+                .collect();
+            elements.sort_unstable();
+            elements.dedup();
+            for &element in elements.iter() {
+                *element_deltas.entry(element).or_default() += delta / elements.len() as u64;
+            }
+        }
+
+        for (&element, total) in self.elements.iter_mut() {
+            let delta = element_deltas.get(&element).copied().unwrap_or_default();
+            *total += delta;
+            report.push((element, usage(delta, *total)));
+        }
+        report
+    }
+}
+
+/// CPU time of the process and stats of its threads, from /proc
+pub fn read_times() -> Option<(u64, Vec<Stat>)> {
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    let process = parse_stat(&stat)?.cpu_time;
+
+    let tasks = std::fs::read_dir("/proc/self/task").ok()?;
+    let threads = tasks
+        .flatten()
+        .filter_map(|task| {
+            let stat = std::fs::read_to_string(task.path().join("stat")).ok()?;
+            parse_stat(&stat)
+        })
+        .collect();
+    Some((process, threads))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EntityExt;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    fn stat(id: u64, cpu_time: u64) -> Stat {
+        Stat {
+            id,
+            start_time: 7,
+            cpu_time,
+        }
+    }
+
+    #[test]
+    fn test_cpu_monitor() {
+        gst::init().unwrap();
+
+        let (a, b) = (REGISTRY.thread(0xc0_0001, 7), REGISTRY.thread(0xc0_0002, 7));
+        let objects: [gst::Bin; 5] = std::array::from_fn(|_| gst::Bin::new());
+        let [pad_a, pad_b, src, filter, sink] = objects.each_ref().map(Entity::from_object);
+
+        let mut monitor = CpuMonitor::default();
+        monitor.assign(pad_a, a, vec![src, filter]);
+        monitor.assign(pad_b, b, vec![filter, sink]);
+
+        let usage = |report: &[(Entity, CpuUsage)], entity: Entity| {
+            report
+                .iter()
+                .find(|(e, _)| *e == entity)
+                .map(|(_, usage)| *usage)
+        };
+        let report = monitor.report(0, 0, &[stat(0xc0_0001, 0), stat(0xc0_0002, 0)]);
+        assert_eq!(usage(&report, filter).unwrap().total, 0);
+
+        // Thread time is split evenly between the elements of the thread
+        let report = monitor.report(
+            SECOND,
+            SECOND,
+            &[stat(0xc0_0001, SECOND / 2), stat(0xc0_0002, SECOND / 4)],
+        );
+        let expected = CpuUsage {
+            percent: 100.0,
+            total: SECOND,
+        };
+        assert_eq!(usage(&report, REGISTRY.session()), Some(expected));
+        assert_eq!(usage(&report, a).unwrap().percent, 50.0);
+        assert_eq!(usage(&report, src).unwrap().total, SECOND / 4);
+        assert_eq!(
+            usage(&report, filter).unwrap().total,
+            SECOND / 4 + SECOND / 8
+        );
+        assert_eq!(usage(&report, sink).unwrap().total, SECOND / 8);
+
+        // Forgotten elements are no longer accounted, totals keep adding up
+        monitor.forget(filter);
+        let report = monitor.report(
+            2 * SECOND,
+            SECOND,
+            &[stat(0xc0_0001, SECOND), stat(0xc0_0002, SECOND / 4)],
+        );
+        assert_eq!(usage(&report, filter), None);
+        assert_eq!(usage(&report, src).unwrap().total, SECOND / 4 + SECOND / 2);
+        assert_eq!(usage(&report, src).unwrap().percent, 50.0);
+        assert_eq!(usage(&report, sink).unwrap().total, SECOND / 8);
+        assert_eq!(usage(&report, sink).unwrap().percent, 0.0);
+
+        // Threads that are gone are no longer reported
+        let report = monitor.report(3 * SECOND, SECOND, &[stat(0xc0_0002, SECOND / 4)]);
+        assert_eq!(usage(&report, a), None);
+        assert!(usage(&report, b).is_some());
+        assert_eq!(monitor.take_exited(), vec![(0xc0_0001, 7)]);
+        assert!(monitor.take_exited().is_empty());
+        assert_eq!(monitor.forget_thread(a), vec![pad_a]);
+        assert!(monitor.forget_thread(a).is_empty());
+    }
+}
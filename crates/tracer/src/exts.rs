@@ -1,30 +1,58 @@
-use glib::ParamFlags;
+use crate::props::{read_properties, PropertyFilter};
+use crate::registry::REGISTRY;
 use pipewerk_common::{comps::*, RecordingStream};
 use gst::glib::{gobject_ffi::g_strdup_value_contents, object::ObjectExt, translate::ToGlibPtr};
 use gst::{prelude::*, Element, Pad};
 use hecs::Entity;
-use log::{error, warn};
-use std::{
-    collections::HashMap,
-    hash::{Hash, Hasher},
-};
+use log::error;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// Taken to attach state to objects, so that it is never replaced while a
+// streaming thread is using it
+static ATTACH_LOCK: Mutex<()> = Mutex::new(());
 
 pub trait EntityExt {
-    fn from_hashable(t: impl Hash) -> Self;
+    fn from_object(object: &impl IsA<glib::Object>) -> Self;
+    fn from_link(src: &Pad, sink: &Pad) -> Self;
 }
 
 impl EntityExt for Entity {
-    fn from_hashable(t: impl Hash) -> Self {
-        let mut s = std::hash::DefaultHasher::new();
-        t.hash(&mut s);
-        let u = s.finish();
-        // We could use Entity::from_bits here, but it could fail if
-        // the hash value is too low and the generation parts ends
-        // up being zero.
-        // The resulting entity may be invalid but this is just for
-        // passing to the app, which will assign another entity before
-        // inserting it in the world.
-        unsafe { std::mem::transmute(u) }
+    fn from_object(object: &impl IsA<glib::Object>) -> Self {
+        REGISTRY.object(object)
+    }
+
+    fn from_link(src: &Pad, sink: &Pad) -> Self {
+        REGISTRY.link(src, sink)
+    }
+}
+
+/// State kept on the objects themselves, so that the streaming threads find
+/// it without going through a map shared by all of them
+pub trait AttachExt {
+    /// State attached to the object under this key, if any
+    fn attached<T: Send + Sync + 'static>(&self, key: glib::Quark) -> Option<&T>;
+
+    /// State attached to the object under this key, attached first if needed
+    fn attach<T: Send + Sync + 'static>(&self, key: glib::Quark, init: impl FnOnce() -> T) -> &T;
+}
+
+impl<O: IsA<glib::Object>> AttachExt for O {
+    fn attached<T: Send + Sync + 'static>(&self, key: glib::Quark) -> Option<&T> {
+        // The state is set once and dropped along with the object, which
+        // outlives the reference it is borrowed from
+        unsafe { self.qdata::<T>(key).map(|state| state.as_ref()) }
+    }
+
+    fn attach<T: Send + Sync + 'static>(&self, key: glib::Quark, init: impl FnOnce() -> T) -> &T {
+        if let Some(state) = self.attached(key) {
+            return state;
+        }
+        let _guard = ATTACH_LOCK.lock().unwrap();
+        if self.attached::<T>(key).is_none() {
+            unsafe { self.set_qdata(key, init()) };
+        }
+        self.attached(key).expect("state was just attached")
     }
 }
 
@@ -44,20 +72,32 @@ impl ValueExt for glib::Value {
 }
 
 pub trait RecordingStreamExt {
-    fn insert_element(&self, element: &Element) -> Entity;
+    fn insert_element(&self, element: &Element, filter: &PropertyFilter) -> Entity;
     fn insert_pad(&self, pad: &Pad) -> Entity;
     fn insert_link(&self, src: &Pad, sink: &Pad, state: State) -> Entity;
+    fn insert_caps(&self, pad: &Pad, caps: &gst::CapsRef);
+    fn insert_session(&self) -> Entity;
 }
 
 impl RecordingStreamExt for RecordingStream {
-    fn insert_element(&self, element: &Element) -> Entity {
+    fn insert_element(&self, element: &Element, filter: &PropertyFilter) -> Entity {
         use glib::object::ObjectExt;
 
-        // elements hashes seem stable so we use that as the id
-        let id = Entity::from_hashable(element);
+        let id = Entity::from_object(element);
 
         self.insert_one(id, Node);
 
+        // Top level elements are grouped under the session, elements added to
+        // a bin get their parent from the bin_add hook
+        if element.parent().is_none() {
+            self.insert_one(
+                id,
+                Child {
+                    parent: REGISTRY.session(),
+                },
+            );
+        }
+
         // FIXME don't add a name component if it's not there
         element
             .property::<Option<glib::GString>>("name")
@@ -72,34 +112,14 @@ impl RecordingStreamExt for RecordingStream {
                 self.insert_one(id, type_name);
             });
 
-        let properties: Properties = element
-            .list_properties()
-            .iter()
-            .filter_map(|p| {
-                let name = p.name().to_string();
-                // Somehow `property_value` segfaults on rtph264pay when called on
-                // non-writable properties, this may be the case on other elements.
-                // Doc states that property_value will panic in that case.
-                // Issue is this trick prevents from forwarding read-only properties
-                // like `stats` of the identity element.
-                // Related: https://discourse.gstreamer.org/t/ximagesink-segfault-on-element-name-custom-rust-tracer/1360
-                if !p.flags().contains(ParamFlags::WRITABLE) {
-                    warn!("Property {name} is not writable, skipping");
-                    return None;
-                }
-
-                let value = format!("{:?}", element.property_value(&name));
-                Some((name, value))
-            })
-            .collect::<HashMap<String, String>>()
-            .into();
+        let properties = read_properties(element, filter);
         self.insert_one(id, properties);
 
         id
     }
 
     fn insert_pad(&self, pad: &Pad) -> Entity {
-        let id = Entity::from_hashable(pad);
+        let id = Entity::from_object(pad);
 
         let port = match pad.direction() {
             gst::PadDirection::Src => Port::Output,
@@ -115,7 +135,7 @@ impl RecordingStreamExt for RecordingStream {
             self.insert_one(
                 id,
                 Child {
-                    parent: Entity::from_hashable(e),
+                    parent: Entity::from_object(e),
                 },
             );
         });
@@ -129,7 +149,7 @@ impl RecordingStreamExt for RecordingStream {
     fn insert_link(&self, src: &Pad, sink: &Pad, state: State) -> Entity {
         let src_id = self.insert_pad(src);
         let sink_id = self.insert_pad(sink);
-        let edge_id = Entity::from_hashable((src, sink));
+        let edge_id = Entity::from_link(src, sink);
         self.insert_one(
             edge_id,
             Edge {
@@ -140,4 +160,47 @@ impl RecordingStreamExt for RecordingStream {
         self.insert_one(edge_id, state);
         edge_id
     }
+
+    fn insert_caps(&self, pad: &Pad, caps: &gst::CapsRef) {
+        let caps = Caps::from(caps);
+        self.insert_one(Entity::from_object(pad), caps.clone());
+
+        // Also attach the caps to the link so that they can be displayed on it
+        if let Some(peer) = pad.peer() {
+            let edge_id = match pad.direction() {
+                gst::PadDirection::Src => Entity::from_link(pad, &peer),
+                _ => Entity::from_link(&peer, pad),
+            };
+            self.insert_one(edge_id, caps);
+        }
+    }
+
+    fn insert_session(&self) -> Entity {
+        let id = REGISTRY.session();
+        let process_name = glib::prgname()
+            .map(|s| s.to_string())
+            .or_else(|| {
+                std::env::current_exe()
+                    .ok()
+                    .and_then(|p| p.file_name().map(|s| s.to_string_lossy().into_owned()))
+            })
+            .unwrap_or_else(|| "unknown".to_string());
+        let pid = std::process::id();
+        let connected_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+
+        self.insert_one(id, Node);
+        self.insert_one(id, Name::from(format!("{process_name} ({pid})")));
+        self.insert_one(
+            id,
+            Session {
+                process_name,
+                pid,
+                host: glib::host_name().to_string(),
+                connected_at,
+            },
+        );
+        id
+    }
 }
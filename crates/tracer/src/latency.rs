@@ -0,0 +1,309 @@
+use crate::{AttachExt, EntityExt};
+use gst::prelude::*;
+use hecs::Entity;
+use once_cell::sync::Lazy;
+use pipewerk_common::{EndToEndLatency, LatencyStats, ProcessingLatency, SpawnOrInsert};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Time after which a buffer that entered an element without leaving it is
+/// forgotten, in nanoseconds
+const MAX_PENDING_AGE: u64 = 10_000_000_000;
+
+static LATENCY: Lazy<glib::Quark> = Lazy::new(|| glib::Quark::from_str("pipewerk-latency"));
+
+struct Arrival {
+    ts: u64,
+    // Time at which the buffer left its source, when it is known
+    origin: Option<u64>,
+}
+
+#[derive(Default)]
+struct ElementLatency {
+    // Buffers that entered the element, by PTS
+    arrivals: HashMap<u64, Arrival>,
+    processing: Vec<u64>,
+    end_to_end: Vec<u64>,
+}
+
+type SharedLatency = Arc<Mutex<ElementLatency>>;
+
+/// Measures the latencies of the elements by matching the PTS of the buffers
+/// they receive with the ones they push, as the `latency` tracer of GStreamer
+/// does with its own metas. Elements changing the PTS of the buffers, and
+/// pads working in pull mode, are not measured. The measures of each element
+/// are attached to it, so that the streaming threads only ever lock the ones
+/// of the elements around the pad they push on.
+#[derive(Default)]
+pub struct LatencyTracker {
+    elements: HashMap<Entity, SharedLatency>,
+}
+
+impl LatencyTracker {
+    /// Measure the latencies of an element from now on
+    pub fn watch(&mut self, element: &gst::Element) {
+        let latency = element.attach(*LATENCY, SharedLatency::default);
+        self.elements
+            .insert(Entity::from_object(element), latency.clone());
+    }
+
+    pub fn forget(&mut self, element: Entity) {
+        self.elements.remove(&element);
+    }
+
+    /// Returns the latencies of the elements that were measured since the
+    /// previous report, to be sent along with the pad statistics
+    pub fn report(&mut self, ts: u64) -> Vec<(Entity, SpawnOrInsert)> {
+        let mut report = Vec::new();
+        for (&element, latency) in self.elements.iter() {
+            let mut latency = latency.lock().unwrap();
+            latency
+                .arrivals
+                .retain(|_, arrival| ts.saturating_sub(arrival.ts) < MAX_PENDING_AGE);
+
+            if let Some(stats) = LatencyStats::from_samples(&mut latency.processing) {
+                report.push((element, ProcessingLatency(stats).into()));
+            }
+            if let Some(stats) = LatencyStats::from_samples(&mut latency.end_to_end) {
+                report.push((element, EndToEndLatency(stats).into()));
+            }
+            latency.processing.clear();
+            latency.end_to_end.clear();
+        }
+        report
+    }
+}
+
+/// Record a buffer pushed on a pad, pushes between ghost and proxy pads
+/// are forwarded by bins and don't count
+pub fn record_push(ts: u64, pad: &gst::Pad, buffer: &gst::BufferRef) {
+    let Some(pts) = buffer.pts() else {
+        return;
+    };
+    if pad.is::<gst::ProxyPad>() {
+        return;
+    }
+    let (Some(producer), Some(consumer)) = (pad.parent_element(), downstream_element(pad)) else {
+        return;
+    };
+    let pts = pts.nseconds();
+
+    // Elements with several source pads, such as tees, push the buffers
+    // they receive on each of them
+    let fans_out = producer.num_src_pads() > 1;
+
+    // Both elements are locked in turn, they can be pushing on other threads
+    let arrival = producer
+        .attached::<SharedLatency>(*LATENCY)
+        .and_then(|latency| {
+            let mut latency = latency.lock().unwrap();
+            let arrival = if fans_out {
+                latency.arrivals.get(&pts).map(|a| (a.ts, a.origin))
+            } else {
+                latency.arrivals.remove(&pts).map(|a| (a.ts, a.origin))
+            };
+            let (arrival_ts, origin) = arrival?;
+            latency.processing.push(ts.saturating_sub(arrival_ts));
+            Some(origin)
+        });
+    let origin = match arrival {
+        Some(origin) => origin,
+        None => (producer.num_sink_pads() == 0).then_some(ts),
+    };
+
+    let Some(latency) = consumer.attached::<SharedLatency>(*LATENCY) else {
+        return;
+    };
+    let is_sink = consumer.num_src_pads() == 0;
+    let mut latency = latency.lock().unwrap();
+    if is_sink {
+        if let Some(origin) = origin {
+            latency.end_to_end.push(ts.saturating_sub(origin));
+        }
+    } else {
+        latency.arrivals.insert(pts, Arrival { ts, origin });
+    }
+}
+
+pub fn record_push_list(ts: u64, pad: &gst::Pad, list: &gst::BufferListRef) {
+    for buffer in list.iter() {
+        record_push(ts, pad, buffer);
+    }
+}
+
+/// Element a buffer pushed on a pad ends up in, looking through ghost pads
+pub fn downstream_element(pad: &gst::Pad) -> Option<gst::Element> {
+    let mut peer = pad.peer()?;
+    loop {
+        // Sink ghost pads forward to their target, source ghost pads are
+        // reached through their internal pad
+        if let Some(ghost) = peer.downcast_ref::<gst::GhostPad>() {
+            peer = ghost.target()?;
+        } else if let Some(ghost) = peer.parent().and_downcast::<gst::GhostPad>() {
+            peer = ghost.peer()?;
+        } else {
+            return peer.parent_element();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gst::PadDirection::{Sink, Src};
+
+    fn element(pads: &[(&str, gst::PadDirection)]) -> gst::Element {
+        let bin = gst::Bin::new();
+        for &(name, direction) in pads {
+            let pad = gst::Pad::builder(direction).name(name).build();
+            bin.add_pad(&pad).unwrap();
+        }
+        bin.upcast()
+    }
+
+    fn pad(element: &gst::Element, name: &str) -> gst::Pad {
+        element.static_pad(name).unwrap()
+    }
+
+    fn buffer(pts: u64) -> gst::Buffer {
+        let mut buffer = gst::Buffer::new();
+        buffer
+            .get_mut()
+            .unwrap()
+            .set_pts(gst::ClockTime::from_nseconds(pts));
+        buffer
+    }
+
+    fn sorted(mut report: Vec<(Entity, SpawnOrInsert)>) -> Vec<(Entity, SpawnOrInsert)> {
+        report.sort_by_key(|(entity, _)| *entity);
+        report
+    }
+
+    fn stats(samples: &[u64]) -> LatencyStats {
+        LatencyStats::from_samples(&mut samples.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_latency() {
+        gst::init().unwrap();
+
+        let src = element(&[("src", Src)]);
+        let filter = element(&[("sink", Sink), ("src", Src)]);
+        let sink = element(&[("sink", Sink)]);
+        pad(&src, "src").link(&pad(&filter, "sink")).unwrap();
+        pad(&filter, "src").link(&pad(&sink, "sink")).unwrap();
+
+        let mut tracker = LatencyTracker::default();
+        for element in [&src, &filter, &sink] {
+            tracker.watch(element);
+        }
+
+        record_push(100, &pad(&src, "src"), &buffer(0));
+        record_push(130, &pad(&filter, "src"), &buffer(0));
+        // Buffers are matched once, pushing the same PTS again measures nothing
+        record_push(140, &pad(&filter, "src"), &buffer(0));
+
+        let expected = vec![
+            (
+                Entity::from_object(&filter),
+                ProcessingLatency(stats(&[30])).into(),
+            ),
+            (
+                Entity::from_object(&sink),
+                EndToEndLatency(stats(&[30])).into(),
+            ),
+        ];
+        assert_eq!(sorted(tracker.report(200)), sorted(expected));
+        assert!(tracker.report(300).is_empty());
+    }
+
+    #[test]
+    fn test_latency_through_ghost_pads() {
+        gst::init().unwrap();
+
+        let bin = gst::Bin::new();
+        let inner = element(&[("sink", Sink), ("src", Src)]);
+        bin.add(&inner).unwrap();
+        let sink_ghost = gst::GhostPad::with_target(&pad(&inner, "sink")).unwrap();
+        let src_ghost = gst::GhostPad::with_target(&pad(&inner, "src")).unwrap();
+        bin.add_pad(&sink_ghost).unwrap();
+        bin.add_pad(&src_ghost).unwrap();
+
+        let src = element(&[("src", Src)]);
+        let sink = element(&[("sink", Sink)]);
+        pad(&src, "src").link(&sink_ghost).unwrap();
+        src_ghost.link(&pad(&sink, "sink")).unwrap();
+        assert_eq!(downstream_element(&pad(&src, "src")), Some(inner.clone()));
+        assert_eq!(downstream_element(&pad(&inner, "src")), Some(sink.clone()));
+
+        let mut tracker = LatencyTracker::default();
+        for element in [&src, bin.upcast_ref(), &inner, &sink] {
+            tracker.watch(element);
+        }
+
+        // Pushes forwarded by the ghost pads don't count
+        record_push(0, &pad(&src, "src"), &buffer(5));
+        record_push(10, src_ghost.upcast_ref(), &buffer(5));
+        record_push(20, &pad(&inner, "src"), &buffer(5));
+
+        let expected = vec![
+            (
+                Entity::from_object(&inner),
+                ProcessingLatency(stats(&[20])).into(),
+            ),
+            (
+                Entity::from_object(&sink),
+                EndToEndLatency(stats(&[20])).into(),
+            ),
+        ];
+        assert_eq!(sorted(tracker.report(100)), sorted(expected));
+    }
+
+    #[test]
+    fn test_latency_forget() {
+        gst::init().unwrap();
+
+        let src = element(&[("src", Src)]);
+        let tee = element(&[("sink", Sink), ("src_0", Src), ("src_1", Src)]);
+        let a = element(&[("sink", Sink)]);
+        let b = element(&[("sink", Sink)]);
+        pad(&src, "src").link(&pad(&tee, "sink")).unwrap();
+        pad(&tee, "src_0").link(&pad(&a, "sink")).unwrap();
+        pad(&tee, "src_1").link(&pad(&b, "sink")).unwrap();
+
+        let mut tracker = LatencyTracker::default();
+        for element in [&src, &tee, &a, &b] {
+            tracker.watch(element);
+        }
+
+        // Tees push the buffers they receive on each of their source pads
+        record_push(0, &pad(&src, "src"), &buffer(0));
+        record_push(10, &pad(&tee, "src_0"), &buffer(0));
+        record_push(20, &pad(&tee, "src_1"), &buffer(0));
+        let expected = vec![
+            (
+                Entity::from_object(&tee),
+                ProcessingLatency(stats(&[10, 20])).into(),
+            ),
+            (
+                Entity::from_object(&a),
+                EndToEndLatency(stats(&[10])).into(),
+            ),
+            (
+                Entity::from_object(&b),
+                EndToEndLatency(stats(&[20])).into(),
+            ),
+        ];
+        assert_eq!(sorted(tracker.report(100)), sorted(expected));
+
+        // Forgotten elements are no longer reported
+        record_push(200, &pad(&src, "src"), &buffer(1));
+        record_push(210, &pad(&tee, "src_0"), &buffer(1));
+        tracker.forget(Entity::from_object(&tee));
+        let expected: Vec<(Entity, SpawnOrInsert)> = vec![(
+            Entity::from_object(&a),
+            EndToEndLatency(stats(&[10])).into(),
+        )];
+        assert_eq!(tracker.report(300), expected);
+    }
+}
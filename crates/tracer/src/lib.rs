@@ -1,7 +1,19 @@
+mod control;
+mod cpu;
 mod exts;
+mod latency;
+mod messages;
+mod preview;
+mod props;
+mod queues;
+mod registry;
+mod stats;
+mod threads;
 mod tracer;
+mod watchdog;
 
 pub use exts::*;
+pub use props::PropertyFilter;
 pub use tracer::*;
 
 mod gst_plugin {
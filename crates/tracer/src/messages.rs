@@ -0,0 +1,58 @@
+use gst::prelude::*;
+use pipewerk_common::{BusMessage, MessageKind};
+
+/// Describe a message posted on the bus, messages of other kinds than the
+/// ones in `MessageKind` are not recorded
+pub fn read_message(message: &gst::MessageRef) -> Option<BusMessage> {
+    use gst::MessageView;
+
+    let (kind, text, details) = match message.view() {
+        MessageView::Error(m) => (
+            MessageKind::Error,
+            m.error().to_string(),
+            m.debug().map(|d| d.to_string()),
+        ),
+        MessageView::Warning(m) => (
+            MessageKind::Warning,
+            m.error().to_string(),
+            m.debug().map(|d| d.to_string()),
+        ),
+        MessageView::Info(m) => (
+            MessageKind::Info,
+            m.error().to_string(),
+            m.debug().map(|d| d.to_string()),
+        ),
+        MessageView::Qos(m) => {
+            let (jitter, proportion, _quality) = m.values();
+            let (processed, dropped) = m.stats();
+            let text = format!(
+                "Jitter {jitter} ns, proportion {proportion:.3}, {processed} processed, {dropped} dropped"
+            );
+            (MessageKind::Qos, text, None)
+        }
+        MessageView::Latency(_) => (MessageKind::Latency, "Latency changed".to_string(), None),
+        MessageView::Buffering(m) => (
+            MessageKind::Buffering,
+            format!("Buffering {}%", m.percent()),
+            None,
+        ),
+        MessageView::StateChanged(m) => {
+            let text = match m.pending() {
+                gst::State::VoidPending => format!("{:?} to {:?}", m.old(), m.current()),
+                pending => format!("{:?} to {:?}, {pending:?} pending", m.old(), m.current()),
+            };
+            (MessageKind::StateChanged, text, None)
+        }
+        _ => return None,
+    };
+
+    Some(BusMessage {
+        kind,
+        source: message
+            .src()
+            .map(|src| src.path_string().to_string())
+            .unwrap_or_default(),
+        text,
+        details,
+    })
+}
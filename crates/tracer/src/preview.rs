@@ -0,0 +1,206 @@
+use crate::EntityExt;
+use anyhow::{Context, Result};
+use gst::prelude::*;
+use hecs::Entity;
+use log::debug;
+use pipewerk_common::{Preview, RecordingStream};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Minimum time between two previews of a pad
+const PREVIEW_INTERVAL: Duration = Duration::from_millis(200);
+/// Size of the box video frames are downscaled to fit in
+const THUMBNAIL_SIZE: (u32, u32) = (160, 120);
+/// Number of slices audio buffers are summarized in
+const AUDIO_SLICES: usize = 64;
+/// Number of bytes sent for buffers of other types
+const DATA_PREVIEW_BYTES: usize = 256;
+
+/// Probes of the pads being sampled, by pad
+#[derive(Default)]
+pub struct Sampler {
+    probes: HashMap<Entity, gst::PadProbeId>,
+}
+
+impl Sampler {
+    pub fn start(&mut self, stream: &RecordingStream, entity: Entity, pad: &gst::Pad) {
+        if self.probes.contains_key(&entity) {
+            return;
+        }
+
+        // The previews are sent from the streaming thread, with the time of
+        // the push that is being probed
+        let stream = stream.clone();
+        let last_sent = Mutex::new(None::<Instant>);
+        let probe = pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST,
+            move |pad, info| {
+                let buffer: &gst::BufferRef = match &info.data {
+                    Some(gst::PadProbeData::Buffer(buffer)) => buffer,
+                    Some(gst::PadProbeData::BufferList(list)) => match list.get(0) {
+                        Some(buffer) => buffer,
+                        None => return gst::PadProbeReturn::Ok,
+                    },
+                    _ => return gst::PadProbeReturn::Ok,
+                };
+
+                {
+                    let mut last_sent = last_sent.lock().unwrap();
+                    if last_sent.is_some_and(|t| t.elapsed() < PREVIEW_INTERVAL) {
+                        return gst::PadProbeReturn::Ok;
+                    }
+                    *last_sent = Some(Instant::now());
+                }
+
+                match preview(pad, buffer) {
+                    Ok(preview) => stream.insert_one(Entity::from_object(pad), preview),
+                    Err(e) => debug!("Failed to preview a buffer of {pad:?}: {e:#}"),
+                }
+                gst::PadProbeReturn::Ok
+            },
+        );
+
+        if let Some(probe) = probe {
+            self.probes.insert(entity, probe);
+        }
+    }
+
+    pub fn stop(&mut self, stream: &RecordingStream, entity: Entity, pad: &gst::Pad) {
+        if let Some(probe) = self.probes.remove(&entity) {
+            pad.remove_probe(probe);
+        }
+        stream.remove_one::<Preview>(entity);
+    }
+}
+
+/// Describe a buffer according to the caps of the pad it goes through
+fn preview(pad: &gst::Pad, buffer: &gst::BufferRef) -> Result<Preview> {
+    if let Some(caps) = pad.current_caps() {
+        // Only buffers in system memory can be mapped
+        let system_memory = caps
+            .features(0)
+            .is_none_or(|f| f.contains(gst::CAPS_FEATURE_MEMORY_SYSTEM_MEMORY));
+        match caps.structure(0).map(|s| s.name().as_str()) {
+            Some("video/x-raw") if system_memory => return video_preview(&caps, buffer),
+            Some("audio/x-raw") => {
+                let info = gst_audio::AudioInfo::from_caps(&caps)?;
+                let map = buffer.map_readable()?;
+                if let Some(preview) = audio_peaks(&info, map.as_slice()) {
+                    return Ok(preview);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let map = buffer.map_readable()?;
+    Ok(Preview::Data {
+        size: map.size() as u64,
+        head: map[..map.size().min(DATA_PREVIEW_BYTES)].to_vec(),
+    })
+}
+
+fn video_preview(caps: &gst::CapsRef, buffer: &gst::BufferRef) -> Result<Preview> {
+    let info = gst_video::VideoInfo::from_caps(caps)?;
+    let scale = f64::min(
+        THUMBNAIL_SIZE.0 as f64 / info.width() as f64,
+        THUMBNAIL_SIZE.1 as f64 / info.height() as f64,
+    )
+    .min(1.0);
+    let width = ((info.width() as f64 * scale) as u32).max(1);
+    let height = ((info.height() as f64 * scale) as u32).max(1);
+
+    let thumbnail_info =
+        gst_video::VideoInfo::builder(gst_video::VideoFormat::Rgba, width, height).build()?;
+    let converter = gst_video::VideoConverter::new(&info, &thumbnail_info, None)?;
+    let frame = gst_video::VideoFrameRef::from_buffer_ref_readable(buffer, &info)?;
+    let mut thumbnail = gst::Buffer::with_size(thumbnail_info.size())?;
+    {
+        let thumbnail = thumbnail.get_mut().context("Buffer is not writable")?;
+        let mut thumbnail_frame =
+            gst_video::VideoFrameRef::from_buffer_ref_writable(thumbnail, &thumbnail_info)?;
+        converter.frame_ref(&frame, &mut thumbnail_frame);
+    }
+
+    let map = thumbnail.map_readable()?;
+    Ok(Preview::Video {
+        width,
+        height,
+        rgba: map.to_vec(),
+    })
+}
+
+/// Peaks of interleaved 16 bits or float samples, other formats are sent as
+/// data
+fn audio_peaks(info: &gst_audio::AudioInfo, data: &[u8]) -> Option<Preview> {
+    if info.layout() != gst_audio::AudioLayout::Interleaved {
+        return None;
+    }
+    let samples: Vec<f32> = match info.format() {
+        gst_audio::AUDIO_FORMAT_S16 => data
+            .chunks_exact(2)
+            .map(|b| i16::from_ne_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect(),
+        gst_audio::AUDIO_FORMAT_F32 => data
+            .chunks_exact(4)
+            .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        _ => return None,
+    };
+
+    let channels = info.channels() as usize;
+    let frames = samples.len() / channels;
+    let frames_per_slice = frames.div_ceil(AUDIO_SLICES).max(1);
+    let peaks = samples
+        .chunks(frames_per_slice * channels)
+        .flat_map(|slice| {
+            (0..channels).map(move |channel| {
+                slice
+                    .iter()
+                    .skip(channel)
+                    .step_by(channels)
+                    .fold(0f32, |peak, sample| peak.max(sample.abs()))
+                    .min(1.0)
+            })
+        })
+        .collect();
+
+    Some(Preview::Audio {
+        channels: channels as u32,
+        peaks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_peaks() {
+        gst::init().unwrap();
+
+        // A stereo ramp over 128 frames, the right channel at half the level
+        let info = gst_audio::AudioInfo::builder(gst_audio::AUDIO_FORMAT_S16, 48000, 2)
+            .build()
+            .unwrap();
+        let data: Vec<u8> = (0..128i16)
+            .flat_map(|i| [i * 256, -i * 128])
+            .flat_map(i16::to_ne_bytes)
+            .collect();
+
+        let Some(Preview::Audio { channels, peaks }) = audio_peaks(&info, &data) else {
+            panic!("Not an audio preview");
+        };
+        assert_eq!(channels, 2);
+        assert_eq!(peaks.len(), AUDIO_SLICES * 2);
+        assert_eq!(peaks[0], 256.0 / 32768.0);
+        assert_eq!(peaks[1], 128.0 / 32768.0);
+        assert_eq!(peaks[peaks.len() - 2], 127.0 * 256.0 / 32768.0);
+
+        let info = gst_audio::AudioInfo::builder(gst_audio::AUDIO_FORMAT_U8, 48000, 1)
+            .build()
+            .unwrap();
+        assert_eq!(audio_peaks(&info, &[0; 16]), None);
+    }
+}
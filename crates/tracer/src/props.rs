@@ -0,0 +1,298 @@
+use anyhow::{bail, Context, Result};
+use glib::{gobject_ffi, translate::*, ParamFlags, Type};
+use gst::prelude::*;
+use log::debug;
+use pipewerk_common::{ParamSpec, Properties, Property, PropertyChange, PropertyValue};
+use std::collections::BTreeMap;
+
+/// Types of values that are never read, samples and buffers keep the media
+/// alive for as long as they are held
+const SKIPPED_TYPES: &[&str] = &["GstSample", "GstBuffer", "GstBufferList"];
+
+/// Properties notified on every buffer by some elements, their changes are
+/// not recorded
+const NOISY_PROPERTIES: &[&str] = &["last-message", "last-sample"];
+
+/// Properties of some factories that crash the process when read from a
+/// tracer, as (factory, property) patterns
+const KNOWN_BAD_PROPERTIES: &[(&str, &str)] = &[
+    ("rtph264pay", "stats"),
+    ("ximagesink", "window-*"),
+    ("xvimagesink", "window-*"),
+];
+
+/// Selects the properties recorded for each element, from the `properties`
+/// and `ignore-properties` tracer params. Both are comma separated lists of
+/// patterns such as `stats`, `identity::stats` or `queue::current-level-*`,
+/// matched against the property name, prefixed with the factory name when
+/// the pattern is.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PropertyFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl PropertyFilter {
+    pub fn from_params(structure: &gst::StructureRef) -> Self {
+        let patterns = |field: &str| -> Vec<String> {
+            structure
+                .get::<String>(field)
+                .map(|s| {
+                    s.split(',')
+                        .map(str::trim)
+                        .filter(|p| !p.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        Self {
+            allow: patterns("properties"),
+            deny: patterns("ignore-properties"),
+        }
+    }
+
+    /// Whether the property of an element made by this factory is recorded,
+    /// everything not denied is when no allow list is given
+    pub fn accepts(&self, factory: &str, property: &str) -> bool {
+        let matches = |pattern: &String| match pattern.split_once("::") {
+            Some((f, p)) => glob_match(f, factory) && glob_match(p, property),
+            None => glob_match(pattern, property),
+        };
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+}
+
+/// Match a pattern where `*` stands for any sequence of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=text.len())
+                .filter(|&i| text.is_char_boundary(i))
+                .any(|i| glob_match(rest, &text[i..]))
+        }
+    }
+}
+
+/// Read the readable properties of an element that pass the filter. The
+/// read-only ones report the internal state of the element, they are only
+/// read once it left the NULL state as some getters expect it to be set up.
+pub fn read_properties(element: &gst::Element, filter: &PropertyFilter) -> Properties {
+    let factory = factory_name(element);
+    let configured = element.current_state() != gst::State::Null;
+    element
+        .list_properties()
+        .iter()
+        .filter(|pspec| is_recorded(&factory, pspec, configured, filter))
+        .filter_map(|pspec| {
+            let value = read_property(element, pspec)?;
+            let property = Property {
+                value: PropertyValue::from(&value),
+                spec: ParamSpec::from(pspec),
+            };
+            Some((pspec.name().to_string(), property))
+        })
+        .collect::<BTreeMap<_, _>>()
+        .into()
+}
+
+/// Read the new value of a property, when notified that it changed
+pub fn read_change(
+    element: &gst::Element,
+    pspec: &glib::ParamSpec,
+    filter: &PropertyFilter,
+) -> Option<PropertyChange> {
+    if NOISY_PROPERTIES.contains(&pspec.name()) {
+        return None;
+    }
+    let configured = element.current_state() != gst::State::Null;
+    if !is_recorded(&factory_name(element), pspec, configured, filter) {
+        return None;
+    }
+    let value = read_property(element, pspec)?;
+    Some(PropertyChange {
+        name: pspec.name().to_string(),
+        value: PropertyValue::from(&value),
+    })
+}
+
+fn is_recorded(
+    factory: &str,
+    pspec: &glib::ParamSpec,
+    configured: bool,
+    filter: &PropertyFilter,
+) -> bool {
+    let name = pspec.name();
+    let known_bad = KNOWN_BAD_PROPERTIES
+        .iter()
+        .any(|(f, p)| glob_match(f, factory) && glob_match(p, name));
+    (configured || pspec.flags().contains(ParamFlags::WRITABLE))
+        && !known_bad
+        && filter.accepts(factory, name)
+}
+
+fn factory_name(element: &gst::Element) -> String {
+    element
+        .factory()
+        .map(|f| f.name().to_string())
+        .unwrap_or_default()
+}
+
+/// Read a property through `g_object_get_property` rather than the bindings,
+/// which panic on anything unexpected. This used to segfault on some elements
+/// when done on every property as they are created, hence the deny list and
+/// the read-only properties being left until the first state change, see
+/// https://discourse.gstreamer.org/t/ximagesink-segfault-on-element-name-custom-rust-tracer/1360
+fn read_property(element: &gst::Element, pspec: &glib::ParamSpec) -> Option<glib::Value> {
+    let flags = pspec.flags();
+    if !flags.contains(ParamFlags::READABLE) || flags.contains(ParamFlags::DEPRECATED) {
+        return None;
+    }
+
+    // Pointers can't be described and some types can't be held by a value
+    let type_ = pspec.value_type();
+    if type_ == Type::POINTER || SKIPPED_TYPES.contains(&type_.name()) {
+        debug!(
+            "Skipping property {} of type {}",
+            pspec.name(),
+            type_.name()
+        );
+        return None;
+    }
+    if unsafe { gobject_ffi::g_type_check_is_value_type(type_.into_glib()) } == 0 {
+        return None;
+    }
+
+    let mut value = glib::Value::from_type(type_);
+    unsafe {
+        gobject_ffi::g_object_get_property(
+            element.as_ptr() as *mut gobject_ffi::GObject,
+            pspec.name().to_glib_none().0,
+            value.to_glib_none_mut().0,
+        );
+    }
+
+    // The getter is free to put something else in the value
+    value.type_().is_a(type_).then_some(value)
+}
+
+/// Set a property from a value sent by the app, converted to the type of
+/// the property. Values of types without a direct counterpart are parsed
+/// from their serialized form.
+pub fn write_property(object: &glib::Object, name: &str, value: &PropertyValue) -> Result<()> {
+    let Some(pspec) = object.find_property(name) else {
+        bail!("No property {name} on {}", object.type_().name());
+    };
+    let flags = pspec.flags();
+    if !flags.contains(ParamFlags::WRITABLE) || flags.contains(ParamFlags::CONSTRUCT_ONLY) {
+        bail!("Property {name} can't be set");
+    }
+
+    let type_ = pspec.value_type();
+    let value = match value {
+        PropertyValue::Bool(v) => v.to_value(),
+        PropertyValue::Int(v) => v.to_value(),
+        PropertyValue::UInt(v) => v.to_value(),
+        PropertyValue::Float(v) => v.to_value(),
+        PropertyValue::String(v) => v.to_value(),
+        PropertyValue::Enum { nick, .. } => glib::EnumClass::with_type(type_)
+            .and_then(|class| class.to_value_by_nick(nick))
+            .with_context(|| format!("Invalid value {nick} for {name}"))?,
+        PropertyValue::Flags { nicks, .. } => glib::FlagsClass::with_type(type_)
+            .and_then(|class| {
+                nicks
+                    .iter()
+                    .fold(class.builder(), |builder, nick| builder.set_by_nick(nick))
+                    .build()
+            })
+            .with_context(|| format!("Invalid flags {} for {name}", nicks.join("+")))?,
+        PropertyValue::Other(s) | PropertyValue::Structure(Some(s)) => {
+            glib::Value::deserialize(s, type_)
+                .with_context(|| format!("Failed to parse {s} as {}", type_.name()))?
+        }
+        v => bail!("Setting {name} to {v} is not supported"),
+    };
+    // Numbers are sent with the widest type of their kind
+    let value = value
+        .transform_with_type(type_)
+        .with_context(|| format!("Can't convert {value:?} to {}", type_.name()))?;
+
+    unsafe {
+        gobject_ffi::g_object_set_property(
+            object.as_ptr(),
+            pspec.name().to_glib_none().0,
+            value.to_glib_none().0,
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_property_filter() {
+        gst::init().unwrap();
+
+        let filter = PropertyFilter::default();
+        assert!(filter.accepts("identity", "stats"));
+
+        let structure = gst::Structure::builder("params")
+            .field(
+                "properties",
+                "identity::stats, queue::current-level-*, name",
+            )
+            .field("ignore-properties", "*::current-level-time")
+            .build();
+        let filter = PropertyFilter::from_params(&structure);
+        assert!(filter.accepts("identity", "stats"));
+        assert!(filter.accepts("queue", "current-level-buffers"));
+        assert!(filter.accepts("videotestsrc", "name"));
+        assert!(!filter.accepts("queue", "current-level-time"));
+        assert!(!filter.accepts("queue", "max-size-buffers"));
+        assert!(!filter.accepts("fakesink", "stats"));
+    }
+
+    #[test]
+    fn test_read_properties() {
+        gst::init().unwrap();
+
+        let filter = PropertyFilter::default();
+        let read = |factory: &str| {
+            let element = gst::ElementFactory::make(factory).build().unwrap();
+            let before = read_properties(&element, &filter);
+            element.set_state(gst::State::Ready).unwrap();
+            let after = read_properties(&element, &filter);
+            element.set_state(gst::State::Null).unwrap();
+            (before, after)
+        };
+
+        // Read-only properties are left alone until the element is set up
+        let (before, after) = read("identity");
+        assert!(before.0.contains_key("name"));
+        assert!(!before.0.contains_key("stats"));
+        assert!(after.0.contains_key("stats"));
+
+        let (_, after) = read("queue");
+        assert!(after.0.contains_key("current-level-buffers"));
+
+        let (before, after) = read("rtph264pay");
+        assert!(before.0.contains_key("mtu"));
+        assert!(!after.0.contains_key("stats"));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("stats", "stats"));
+        assert!(!glob_match("stats", "stats2"));
+        assert!(glob_match("current-*", "current-level-bytes"));
+        assert!(glob_match("*-level-*", "current-level-bytes"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("max-*", "current-level-bytes"));
+    }
+}
@@ -0,0 +1,107 @@
+use crate::EntityExt;
+use gst::prelude::*;
+use hecs::Entity;
+use pipewerk_common::QueueLevel;
+use std::collections::HashMap;
+
+/// Factories of the elements whose fill level is monitored
+const QUEUE_FACTORIES: &[&str] = &["queue", "queue2", "multiqueue"];
+
+struct Queue {
+    element: glib::WeakRef<gst::Element>,
+    full_since: Option<u64>,
+    empty_since: Option<u64>,
+}
+
+/// Samples the fill level of the queues of the pipeline, periodically rather
+/// than from the hooks as a full queue blocks the threads around it
+#[derive(Default)]
+pub struct QueueMonitor {
+    queues: HashMap<Entity, Queue>,
+}
+
+impl QueueMonitor {
+    pub fn watch(&mut self, element: &gst::Element) {
+        let is_queue = element
+            .factory()
+            .is_some_and(|f| QUEUE_FACTORIES.contains(&f.name().as_str()));
+        if is_queue {
+            self.queues.insert(
+                Entity::from_object(element),
+                Queue {
+                    element: element.downgrade(),
+                    full_since: None,
+                    empty_since: None,
+                },
+            );
+        }
+    }
+
+    pub fn forget(&mut self, element: Entity) {
+        self.queues.remove(&element);
+    }
+
+    /// Elements to sample, the properties are read without holding the
+    /// monitor as reading them takes the lock of the queue
+    pub fn elements(&self) -> Vec<(Entity, gst::Element)> {
+        self.queues
+            .iter()
+            .filter_map(|(&id, queue)| Some((id, queue.element.upgrade()?)))
+            .collect()
+    }
+
+    /// Update how long the queue has been full or empty for
+    pub fn update(&mut self, ts: u64, id: Entity, mut level: QueueLevel) -> Option<QueueLevel> {
+        let queue = self.queues.get_mut(&id)?;
+        let since = |since: &mut Option<u64>, active: bool| {
+            if !active {
+                *since = None;
+                return 0;
+            }
+            ts.saturating_sub(*since.get_or_insert(ts))
+        };
+        level.full_for = since(&mut queue.full_since, level.is_full());
+        level.empty_for = since(&mut queue.empty_since, level.is_empty());
+        Some(level)
+    }
+}
+
+/// Read the current level of a queue, multiqueues are reported with the level
+/// of their fullest single queue
+pub fn read_level(element: &gst::Element) -> QueueLevel {
+    let limits = QueueLevel {
+        max_buffers: read_u64(element, "max-size-buffers"),
+        max_bytes: read_u64(element, "max-size-bytes"),
+        max_time: read_u64(element, "max-size-time"),
+        ..Default::default()
+    };
+    let level_of = |object: &glib::Object| QueueLevel {
+        buffers: read_u64(object, "current-level-buffers"),
+        bytes: read_u64(object, "current-level-bytes"),
+        time: read_u64(object, "current-level-time"),
+        ..limits
+    };
+
+    if element.find_property("current-level-buffers").is_some() {
+        return level_of(element.upcast_ref());
+    }
+    element
+        .sink_pads()
+        .iter()
+        .map(|pad| level_of(pad.upcast_ref()))
+        .max_by(|a, b| a.fill().total_cmp(&b.fill()))
+        .unwrap_or(limits)
+}
+
+/// Read an unsigned property of any size, missing properties read as 0
+fn read_u64(object: &impl IsA<glib::Object>, name: &str) -> u64 {
+    if object.find_property(name).is_none() {
+        return 0;
+    }
+    object
+        .property_value(name)
+        .transform::<u64>()
+        .ok()
+        .and_then(|v| v.get::<u64>().ok())
+        .unwrap_or_default()
+}
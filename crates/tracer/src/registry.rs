@@ -0,0 +1,210 @@
+use glib::prelude::*;
+use hecs::Entity;
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, sync::Mutex};
+
+/// Process-wide registry, objects are identified by their address which is
+/// unique for as long as they are alive
+pub static REGISTRY: Lazy<EntityRegistry> = Lazy::new(EntityRegistry::default);
+
+/// Hands out entity ids to gstreamer objects and links. Ids are released when
+/// the objects are destroyed and reused with a new generation, so that an
+/// object allocated at the address of a destroyed one is never mistaken for it.
+#[derive(Default)]
+pub struct EntityRegistry {
+    inner: Mutex<Allocator>,
+}
+
+impl EntityRegistry {
+    pub fn object(&self, object: &impl IsA<glib::Object>) -> Entity {
+        let object = object.as_ref();
+        self.object_at(object.as_ptr() as usize, Some(object))
+    }
+
+    /// Object with this id, if it is still alive
+    pub fn lookup(&self, entity: Entity) -> Option<glib::Object> {
+        self.inner.lock().unwrap().weak_refs.get(&entity)?.upgrade()
+    }
+
+    /// Id of the traced process itself, the parent of top level elements
+    pub fn session(&self) -> Entity {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(entity) = inner.session {
+            return entity;
+        }
+        let entity = inner.allocate();
+        inner.session = Some(entity);
+        entity
+    }
+
+    /// Id of an OS thread, known by its id and its start time as the system
+    /// reuses the ids of the threads that are gone
+    pub fn thread(&self, tid: u64, start_time: u64) -> Entity {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(&entity) = inner.threads.get(&(tid, start_time)) {
+            return entity;
+        }
+        let entity = inner.allocate();
+        inner.threads.insert((tid, start_time), entity);
+        entity
+    }
+
+    /// Release the id of an OS thread that is gone, if it was seen
+    pub fn release_thread(&self, tid: u64, start_time: u64) -> Option<Entity> {
+        let mut inner = self.inner.lock().unwrap();
+        let entity = inner.threads.remove(&(tid, start_time))?;
+        inner.free(entity);
+        Some(entity)
+    }
+
+    /// Id of an OS thread that was already seen
+    pub fn find_thread(&self, tid: u64, start_time: u64) -> Option<Entity> {
+        let inner = self.inner.lock().unwrap();
+        inner.threads.get(&(tid, start_time)).copied()
+    }
+
+    pub fn link(&self, src: &gst::Pad, sink: &gst::Pad) -> Entity {
+        self.link_between(src.as_ptr() as usize, sink.as_ptr() as usize)
+    }
+
+    pub fn release_link(&self, src: &gst::Pad, sink: &gst::Pad) -> Option<Entity> {
+        let mut inner = self.inner.lock().unwrap();
+        let key = (src.as_ptr() as usize, sink.as_ptr() as usize);
+        let entity = inner.links.remove(&key)?;
+        inner.free(entity);
+        Some(entity)
+    }
+
+    /// Release the id of the object at this address along with the ids of the
+    /// links it is part of, and return them
+    pub fn release(&self, address: usize) -> Vec<Entity> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let mut released = Vec::new();
+        inner.links.retain(|&(src, sink), &mut entity| {
+            let retain = src != address && sink != address;
+            if !retain {
+                released.push(entity);
+            }
+            retain
+        });
+        released.extend(inner.objects.remove(&address));
+
+        for &entity in released.iter() {
+            inner.free(entity);
+            inner.weak_refs.remove(&entity);
+        }
+        released
+    }
+
+    fn object_at(&self, address: usize, object: Option<&glib::Object>) -> Entity {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(&entity) = inner.objects.get(&address) {
+            return entity;
+        }
+        let entity = inner.allocate();
+        inner.objects.insert(address, entity);
+        if let Some(object) = object {
+            inner.weak_refs.insert(entity, object.downgrade());
+        }
+        entity
+    }
+
+    fn link_between(&self, src: usize, sink: usize) -> Entity {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(&entity) = inner.links.get(&(src, sink)) {
+            return entity;
+        }
+        let entity = inner.allocate();
+        inner.links.insert((src, sink), entity);
+        entity
+    }
+}
+
+#[derive(Default)]
+struct Allocator {
+    objects: HashMap<usize, Entity>,
+    // Objects by id, to find the targets of the controls sent by the app
+    weak_refs: HashMap<Entity, glib::WeakRef<glib::Object>>,
+    links: HashMap<(usize, usize), Entity>,
+    // By thread id and start time
+    threads: HashMap<(u64, u64), Entity>,
+    session: Option<Entity>,
+    // Released ids with the generation to use when they are handed out again
+    free_list: Vec<(u32, u32)>,
+    next_id: u32,
+}
+
+impl Allocator {
+    fn allocate(&mut self) -> Entity {
+        let (id, generation) = self.free_list.pop().unwrap_or_else(|| {
+            let id = self.next_id;
+            self.next_id += 1;
+            (id, 1)
+        });
+        Entity::from_bits((u64::from(generation) << 32) | u64::from(id))
+            .expect("generation is never zero")
+    }
+
+    fn free(&mut self, entity: Entity) {
+        let generation = (entity.to_bits().get() >> 32) as u32;
+        let generation = generation.checked_add(1).unwrap_or(1);
+        self.free_list.push((entity.id(), generation));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_stable_and_unique() {
+        let registry = EntityRegistry::default();
+        let a = registry.object_at(0x1000, None);
+        let b = registry.object_at(0x2000, None);
+        assert_ne!(a, b);
+        assert_eq!(registry.object_at(0x1000, None), a);
+
+        let link = registry.link_between(0x1000, 0x2000);
+        assert_ne!(link, a);
+        assert_ne!(link, b);
+        assert_eq!(registry.link_between(0x1000, 0x2000), link);
+
+        let session = registry.session();
+        assert!(![a, b, link].contains(&session));
+        assert_eq!(registry.session(), session);
+
+        assert_eq!(registry.find_thread(0x1000, 10), None);
+        let thread = registry.thread(0x1000, 10);
+        assert!(![a, b, link, session].contains(&thread));
+        assert_eq!(registry.thread(0x1000, 10), thread);
+        assert_eq!(registry.find_thread(0x1000, 10), Some(thread));
+
+        // A thread reusing the id of a thread that is gone is another one
+        assert_eq!(registry.find_thread(0x1000, 20), None);
+        assert_ne!(registry.thread(0x1000, 20), thread);
+        assert_eq!(registry.release_thread(0x1000, 10), Some(thread));
+        assert_eq!(registry.find_thread(0x1000, 10), None);
+        assert_eq!(registry.release_thread(0x1000, 10), None);
+    }
+
+    #[test]
+    fn test_reused_address_gets_new_generation() {
+        let registry = EntityRegistry::default();
+        let a = registry.object_at(0x1000, None);
+        let link = registry.link_between(0x1000, 0x2000);
+
+        let released = registry.release(0x1000);
+        assert_eq!(released.len(), 2);
+        assert!(released.contains(&a));
+        assert!(released.contains(&link));
+
+        // Releasing an unknown address does nothing
+        assert!(registry.release(0x3000).is_empty());
+
+        let reused = registry.object_at(0x1000, None);
+        assert_ne!(reused, a);
+        assert_ne!(reused, link);
+        assert!(reused.id() == a.id() || reused.id() == link.id());
+    }
+}
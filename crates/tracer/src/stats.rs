@@ -0,0 +1,109 @@
+use crate::{AttachExt, EntityExt};
+use hecs::Entity;
+use once_cell::sync::Lazy;
+use pipewerk_common::PadStats;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Minimum time between two reports of the pad statistics, in nanoseconds
+pub const STATS_INTERVAL: u64 = 1_000_000_000;
+
+static COUNTERS: Lazy<glib::Quark> = Lazy::new(|| glib::Quark::from_str("pipewerk-flow-stats"));
+
+#[derive(Default)]
+struct PadCounters {
+    buffers: u64,
+    bytes: u64,
+    last_pts: Option<u64>,
+    // Totals at the time of the previous report, used to compute the rates
+    reported_buffers: u64,
+    reported_bytes: u64,
+    idle: bool,
+}
+
+type SharedCounters = Arc<Mutex<PadCounters>>;
+
+/// Accumulates the buffers going through the pads between two reports. The
+/// counters of each pad are attached to it, the streaming threads only ever
+/// lock the ones of their own pads.
+#[derive(Default)]
+pub struct FlowStats {
+    pads: HashMap<Entity, SharedCounters>,
+    last_report: u64,
+}
+
+impl FlowStats {
+    /// Count the buffers going through a pad from now on
+    pub fn watch(&mut self, pad: &gst::Pad) {
+        let counters = pad.attach(*COUNTERS, SharedCounters::default);
+        self.pads.insert(Entity::from_object(pad), counters.clone());
+    }
+
+    pub fn forget(&mut self, pad: Entity) {
+        self.pads.remove(&pad);
+    }
+
+    /// Returns the stats of the pads that have seen buffers since the previous
+    /// report, or that have just gone idle, once every `STATS_INTERVAL`
+    pub fn report(&mut self, ts: u64) -> Option<Vec<(Entity, PadStats)>> {
+        let elapsed = ts.saturating_sub(self.last_report);
+        if elapsed < STATS_INTERVAL {
+            return None;
+        }
+        self.last_report = ts;
+
+        let seconds = elapsed as f64 / 1e9;
+        let report = self
+            .pads
+            .iter()
+            .filter_map(|(&pad, counters)| {
+                let mut c = counters.lock().unwrap();
+                let new_buffers = c.buffers - c.reported_buffers;
+                let new_bytes = c.bytes - c.reported_bytes;
+                c.reported_buffers = c.buffers;
+                c.reported_bytes = c.bytes;
+
+                // Idle pads are reported once with null rates, then skipped
+                // until buffers are flowing again. Pads that never saw any
+                // are not reported.
+                if new_buffers == 0 && (c.idle || c.buffers == 0) {
+                    return None;
+                }
+                c.idle = new_buffers == 0;
+
+                let stats = PadStats {
+                    buffers: c.buffers,
+                    bytes: c.bytes,
+                    buffers_per_sec: new_buffers as f64 / seconds,
+                    bytes_per_sec: new_bytes as f64 / seconds,
+                    last_pts: c.last_pts,
+                };
+                Some((pad, stats))
+            })
+            .collect();
+
+        Some(report)
+    }
+}
+
+/// Count a buffer going through a pad, pads that are not watched are skipped
+pub fn record_buffer(pad: &gst::Pad, buffer: &gst::BufferRef) {
+    if let Some(counters) = pad.attached::<SharedCounters>(*COUNTERS) {
+        count(&mut counters.lock().unwrap(), buffer);
+    }
+}
+
+pub fn record_buffer_list(pad: &gst::Pad, list: &gst::BufferListRef) {
+    if let Some(counters) = pad.attached::<SharedCounters>(*COUNTERS) {
+        let mut counters = counters.lock().unwrap();
+        for buffer in list.iter() {
+            count(&mut counters, buffer);
+        }
+    }
+}
+
+fn count(counters: &mut PadCounters, buffer: &gst::BufferRef) {
+    counters.buffers += 1;
+    counters.bytes += buffer.size() as u64;
+    counters.last_pts = buffer.pts().map(|t| t.nseconds()).or(counters.last_pts);
+}
@@ -0,0 +1,104 @@
+use crate::registry::REGISTRY;
+use crate::AttachExt;
+use hecs::Entity;
+use once_cell::sync::Lazy;
+use pipewerk_common::{RecordingStream, ThreadInfo};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+thread_local! {
+    // Entity of the calling thread once it has been looked up, `None` inside
+    // when its id can't be read
+    static CURRENT: Cell<Option<Option<Entity>>> = const { Cell::new(None) };
+}
+
+/// Unit of the times in /proc, USER_HZ is 100 unless the system says otherwise
+static NANOS_PER_TICK: Lazy<u64> = Lazy::new(|| {
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    1_000_000_000 / if ticks > 0 { ticks as u64 } else { 100 }
+});
+
+/// Entity of the calling thread, its id and name are sent the first time it
+/// is seen. They are read from /proc, threads are not tracked elsewhere.
+pub fn current(stream: &RecordingStream) -> Option<Entity> {
+    match CURRENT.get() {
+        Some(entity) => entity,
+        None => refresh(stream),
+    }
+}
+
+/// Read the name of the calling thread again, the threads of the task pool
+/// are renamed after the pad of each task they run
+pub fn refresh(stream: &RecordingStream) -> Option<Entity> {
+    let entity = read_thread_info().map(|(info, start_time)| {
+        let entity = REGISTRY.thread(info.tid, start_time);
+        stream.insert_one(entity, info);
+        entity
+    });
+    CURRENT.set(Some(entity));
+    entity
+}
+
+/// Id and name of the calling thread, along with its start time
+fn read_thread_info() -> Option<(ThreadInfo, u64)> {
+    let stat = std::fs::read_to_string("/proc/thread-self/stat").ok()?;
+    let stat = parse_stat(&stat)?;
+    let name = std::fs::read_to_string("/proc/thread-self/comm")
+        .map(|name| name.trim_end().to_string())
+        .unwrap_or_default();
+    Some((ThreadInfo { tid: stat.id, name }, stat.start_time))
+}
+
+/// Fields of the stat file of a process or a thread
+#[derive(Debug, PartialEq)]
+pub struct Stat {
+    pub id: u64,
+    /// Time the thread started after boot, in clock ticks. Thread ids are
+    /// reused by the system, but not along with their start time.
+    pub start_time: u64,
+    /// CPU time used, user and system, in nanoseconds
+    pub cpu_time: u64,
+}
+
+pub fn parse_stat(stat: &str) -> Option<Stat> {
+    let (id, rest) = stat.split_once(" (")?;
+    // The name can contain anything, including parentheses
+    let (_, fields) = rest.rsplit_once(") ")?;
+    let fields: Vec<&str> = fields.split_whitespace().collect();
+    // The state is the third field of the file, the times its 14th and 15th
+    // and the start time its 22nd
+    let field = |index: usize| -> Option<u64> { fields.get(index)?.parse().ok() };
+    Some(Stat {
+        id: id.parse().ok()?,
+        start_time: field(19)?,
+        cpu_time: (field(11)? + field(12)?) * *NANOS_PER_TICK,
+    })
+}
+
+static PAD_THREAD: Lazy<glib::Quark> = Lazy::new(|| glib::Quark::from_str("pipewerk-thread"));
+
+/// Record the thread pushing on a pad, attached to the pad as the bits of
+/// its entity. Returns whether it is not the one last seen on it.
+pub fn update_pad(pad: &gst::Pad, thread: Entity) -> bool {
+    let bits = thread.to_bits().get();
+    let last = pad.attach(*PAD_THREAD, AtomicU64::default);
+    last.swap(bits, Ordering::Relaxed) != bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let stat = "4242 (queue0:src (1)) S 4200 4200 4200 0 -1 4194368 \
+                    120 0 0 0 35 7 0 0 20 0 12 0 1000 0 0";
+        let expected = Stat {
+            id: 4242,
+            start_time: 1000,
+            cpu_time: 42 * *NANOS_PER_TICK,
+        };
+        assert_eq!(parse_stat(stat), Some(expected));
+        assert_eq!(parse_stat("4242 (truncated) S 1 2"), None);
+    }
+}
@@ -6,15 +6,27 @@ glib::wrapper! {
 }
 
 mod imp {
-    use crate::EntityExt;
-    use pipewerk_common::{Child, RecordingStream, State};
+    use crate::{
+        control, messages::read_message, props::read_change, registry::REGISTRY, EntityExt,
+        PropertyFilter,
+    };
+    use pipewerk_common::{Child, RecordingStream, Stalled, State, Thread};
     use gst::{glib, prelude::*, subclass::prelude::*};
     use hecs::Entity;
     use log::*;
     use once_cell::sync::Lazy;
-    use std::{net::Ipv4Addr, str::FromStr};
+    use std::{net::Ipv4Addr, str::FromStr, sync::Mutex, time::Duration};
 
+    use crate::cpu::{self, CpuMonitor};
     use crate::exts::RecordingStreamExt;
+    use crate::latency::{self, downstream_element, LatencyTracker};
+    use crate::queues::{read_level, QueueMonitor};
+    use crate::stats::{self, FlowStats};
+    use crate::threads;
+    use crate::watchdog::{self, read_pending, Watchdog};
+
+    /// Time between two samples of what can't be measured from the hooks
+    const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
 
     static _CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
         gst::DebugCategory::new(
@@ -24,8 +36,130 @@ mod imp {
         )
     });
 
+    /// The monitors keep the state of each pad or element on the objects
+    /// themselves, the streaming threads don't take their locks. They are
+    /// taken when objects come and go, and by the sampler.
     pub struct PipewerkTracer {
         pub stream: pipewerk_common::RecordingStream,
+        stats: Mutex<FlowStats>,
+        latency: Mutex<LatencyTracker>,
+        queues: Mutex<QueueMonitor>,
+        cpu: Mutex<CpuMonitor>,
+        watchdog: Mutex<Watchdog>,
+        // Elements being removed from a bin, as (bin, element), between the
+        // pre and post hooks
+        pending_removals: Mutex<Vec<(Entity, Entity)>>,
+        property_filter: Mutex<PropertyFilter>,
+    }
+
+    impl PipewerkTracer {
+        fn report_stats(&self, ts: u64) {
+            let Some(report) = self.stats.lock().unwrap().report(ts) else {
+                return;
+            };
+            for (id, stats) in report {
+                self.stream.insert_one(id, stats);
+            }
+            for (id, latency) in self.latency.lock().unwrap().report(ts) {
+                self.stream.insert_one(id, latency);
+            }
+        }
+
+        /// Report the statistics, sample the queues and the CPU usage and check
+        /// for stalls, on a thread of its own as no hook is called anymore once
+        /// the pipeline is blocked, and to keep all of it off the streaming
+        /// threads
+        fn spawn_sampler(&self) {
+            let tracer = self.obj().downgrade();
+            std::thread::Builder::new()
+                .name("pipewerk-sampler".into())
+                .spawn(move || loop {
+                    std::thread::sleep(SAMPLE_INTERVAL);
+                    let Some(tracer) = tracer.upgrade() else {
+                        break;
+                    };
+                    tracer.imp().sample();
+                })
+                .expect("Failed to spawn the sampler thread");
+        }
+
+        fn sample(&self) {
+            // Samples are stamped on the clock of the hooks, which is only
+            // known once the first one is seen
+            let Some(ts) = self.stream.now() else {
+                return;
+            };
+            self.stream.set_time(ts);
+            self.report_stats(ts);
+
+            // Properties and states are read without holding the monitors,
+            // as reading them takes the lock of the element
+            let queues = self.queues.lock().unwrap().elements();
+            let levels: Vec<_> = queues
+                .iter()
+                .map(|(id, element)| (*id, read_level(element)))
+                .collect();
+            let mut monitor = self.queues.lock().unwrap();
+            for (id, level) in levels {
+                if let Some(level) = monitor.update(ts, id, level) {
+                    self.stream.insert_one(id, level);
+                }
+            }
+            drop(monitor);
+
+            // /proc is read without holding the monitor
+            if let Some((process, threads)) = cpu::read_times() {
+                let mut monitor = self.cpu.lock().unwrap();
+                for (id, usage) in monitor.report(ts, process, &threads) {
+                    self.stream.insert_one(id, usage);
+                }
+                for (tid, start_time) in monitor.take_exited() {
+                    let Some(thread) = REGISTRY.release_thread(tid, start_time) else {
+                        continue;
+                    };
+                    for pad in monitor.forget_thread(thread) {
+                        self.stream.remove_one::<Thread>(pad);
+                    }
+                    self.stream.despawn(thread);
+                }
+            }
+
+            let elements = self.watchdog.lock().unwrap().elements();
+            let pending: Vec<_> = elements
+                .iter()
+                .map(|(id, element)| (*id, read_pending(element)))
+                .collect();
+            for (id, stalled) in self.watchdog.lock().unwrap().check(ts, &pending) {
+                match stalled {
+                    Some(stalled) => self.stream.insert_one(id, stalled),
+                    None => self.stream.remove_one::<Stalled>(id),
+                }
+            }
+        }
+
+        /// Link the pad to the calling thread, which is pushing buffers out of it
+        fn record_thread(&self, pad: &gst::Pad) {
+            let Some(thread) = threads::current(&self.stream) else {
+                return;
+            };
+            if threads::update_pad(pad, thread) {
+                let id = Entity::from_object(pad);
+                self.stream.insert_one(id, Thread { thread });
+
+                // Both ends of the push run on the thread
+                let elements = [pad.parent_element(), downstream_element(pad)]
+                    .iter()
+                    .flatten()
+                    .map(Entity::from_object)
+                    .collect();
+                self.cpu.lock().unwrap().assign(id, thread, elements);
+            }
+        }
+
+        fn insert_element(&self, element: &gst::Element) -> Entity {
+            let filter = self.property_filter.lock().unwrap();
+            self.stream.insert_element(element, &filter)
+        }
     }
 
     #[glib::object_subclass]
@@ -37,6 +171,13 @@ mod imp {
         fn new() -> Self {
             Self {
                 stream: RecordingStream::new(),
+                stats: Default::default(),
+                latency: Default::default(),
+                queues: Default::default(),
+                cpu: Default::default(),
+                watchdog: Default::default(),
+                pending_removals: Default::default(),
+                property_filter: Default::default(),
             }
         }
     }
@@ -66,30 +207,82 @@ mod imp {
                 } else if let Ok(p) = structure.get::<i32>("port") {
                     port = p as u16;
                 }
+
+                *self.property_filter.lock().unwrap() = PropertyFilter::from_params(&structure);
+
+                if let Ok(ms) = structure.get::<i32>("stall-timeout") {
+                    let timeout = ms.max(0) as u64 * 1_000_000;
+                    self.watchdog.lock().unwrap().set_timeout(timeout);
+                }
             };
 
             debug!("Connecting to {ip}:{port}");
-            self.stream.connect(ip, port);
+            let controls = self.stream.connect(ip, port);
+            control::spawn(self.stream.clone(), controls);
+            self.spawn_sampler();
+            self.stream.insert_session();
 
             self.parent_constructed();
             self.register_hook(TracerHook::BinAddPost);
+            self.register_hook(TracerHook::BinRemovePost);
+            self.register_hook(TracerHook::BinRemovePre);
             self.register_hook(TracerHook::ElementAddPad);
             self.register_hook(TracerHook::ElementChangeStatePost);
             self.register_hook(TracerHook::ElementNew);
+            self.register_hook(TracerHook::ElementPostMessagePre);
+            self.register_hook(TracerHook::ElementRemovePad);
+            self.register_hook(TracerHook::ObjectDestroyed);
             self.register_hook(TracerHook::PadLinkPost);
             self.register_hook(TracerHook::PadLinkPre);
+            self.register_hook(TracerHook::PadUnlinkPost);
+            self.register_hook(TracerHook::PadPullRangePost);
+            self.register_hook(TracerHook::PadPushEventPre);
+            self.register_hook(TracerHook::PadPushListPre);
+            self.register_hook(TracerHook::PadPushPre);
         }
     }
 
     impl GstObjectImpl for PipewerkTracer {}
 
     impl TracerImpl for PipewerkTracer {
-        fn element_add_pad(&self, _ts: u64, element: &gst::Element, pad: &gst::Pad) {
+        fn element_add_pad(&self, ts: u64, element: &gst::Element, pad: &gst::Pad) {
+            self.stream.set_time(ts);
             // We're receiving events in a way that doesn't seem logical, for instance
             // in the case of decodebin pads are linked before being added, etc.
             // To account for that we always tentatively create related entities...
-            self.stream.insert_element(element);
+            self.insert_element(element);
             self.stream.insert_pad(pad);
+            self.stats.lock().unwrap().watch(pad);
+
+            // Caps events cover what is being sent, the property covers what
+            // the pad actually accepted. The notification comes from the
+            // streaming thread, which carries the time of the hook that
+            // triggered it.
+            let stream = self.stream.clone();
+            pad.connect_notify(Some("caps"), move |pad, _| {
+                if let Some(caps) = pad.current_caps() {
+                    stream.insert_caps(pad, &caps);
+                }
+            });
+        }
+
+        fn element_remove_pad(&self, ts: u64, _element: &gst::Element, pad: &gst::Pad) {
+            self.stream.set_time(ts);
+            if let Some(peer) = pad.peer() {
+                let edge_id = match pad.direction() {
+                    gst::PadDirection::Src => REGISTRY.release_link(pad, &peer),
+                    _ => REGISTRY.release_link(&peer, pad),
+                };
+                if let Some(id) = edge_id {
+                    self.stream.despawn(id);
+                }
+            }
+
+            // The pad keeps its id until it is destroyed, in case it is added back
+            let id = Entity::from_object(pad);
+            self.stats.lock().unwrap().forget(id);
+            self.watchdog.lock().unwrap().forget(id);
+            self.stream.despawn(id);
         }
 
         fn element_change_state_post(
@@ -99,8 +292,9 @@ mod imp {
             change: gst::StateChange,
             result: Result<gst::StateChangeSuccess, gst::StateChangeError>,
         ) {
+            self.stream.set_time(ts);
             if result.is_ok() {
-                let id = Entity::from_hashable(element);
+                let id = Entity::from_object(element);
                 let new_state = match change {
                     gst::StateChange::NullToReady => State::Ready,
                     gst::StateChange::ReadyToPaused => State::Paused,
@@ -110,8 +304,12 @@ mod imp {
                     gst::StateChange::ReadyToNull => State::Null,
                     _ => return,
                 };
-                self.stream.insert_element(element);
+                self.insert_element(element);
                 self.stream.insert_one(id, new_state);
+                self.watchdog
+                    .lock()
+                    .unwrap()
+                    .set_playing(ts, id, new_state == State::Playing);
             } else {
                 error!(
                     "Element {:?} failed to change state to {:?} at ts {}",
@@ -120,38 +318,205 @@ mod imp {
             }
         }
 
-        fn element_new(&self, _ts: u64, element: &gst::Element) {
-            self.stream.insert_element(element);
+        fn element_new(&self, ts: u64, element: &gst::Element) {
+            self.stream.set_time(ts);
+            self.insert_element(element);
+            self.latency.lock().unwrap().watch(element);
+            self.queues.lock().unwrap().watch(element);
+            self.watchdog.lock().unwrap().watch(element);
+
+            // Properties changed afterwards are sent one at a time. They can
+            // be changed from any thread, long after its last hook.
+            let stream = self.stream.clone();
+            let filter = self.property_filter.lock().unwrap().clone();
+            element.connect_notify(None, move |element, pspec| {
+                if let Some(change) = read_change(element, pspec, &filter) {
+                    if let Some(ts) = stream.now() {
+                        stream.set_time(ts);
+                    }
+                    stream.insert_one(Entity::from_object(element), change);
+                }
+            });
         }
 
-        fn bin_add_post(&self, _ts: u64, bin: &gst::Bin, element: &gst::Element, _success: bool) {
-            self.stream.insert_element(bin.as_ref());
-            self.stream.insert_element(element);
+        fn element_post_message_pre(
+            &self,
+            ts: u64,
+            element: &gst::Element,
+            message: &gst::Message,
+        ) {
+            self.stream.set_time(ts);
+
+            // Tasks announce themselves from their thread when they start,
+            // which may have been renamed since it was last seen
+            if let gst::MessageView::StreamStatus(status) = message.view() {
+                let (type_, owner) = status.get();
+                if type_ == gst::StreamStatusType::Enter && owner == *element {
+                    threads::refresh(&self.stream);
+                    let pad = message.src().and_then(|src| src.downcast_ref::<gst::Pad>());
+                    if let Some(pad) = pad {
+                        self.record_thread(pad);
+                    }
+                }
+            }
+
+            // Bins post the messages of their children again, only the
+            // original post is recorded
+            if message.src() != Some(element.upcast_ref()) {
+                return;
+            }
+            // The element exists since `element_new`, reading its properties
+            // again for every QoS message would be too costly
+            if let Some(message) = read_message(message) {
+                self.stream
+                    .insert_one(Entity::from_object(element), message);
+            }
+        }
+
+        fn bin_add_post(&self, ts: u64, bin: &gst::Bin, element: &gst::Element, _success: bool) {
+            self.stream.set_time(ts);
+            self.insert_element(bin.as_ref());
+            self.insert_element(element);
             self.stream.insert_one(
-                Entity::from_hashable(element),
+                Entity::from_object(element),
                 Child {
-                    parent: Entity::from_hashable(bin),
+                    parent: Entity::from_object(bin),
                 },
             )
         }
 
-        fn pad_link_pre(&self, _ts: u64, src: &gst::Pad, sink: &gst::Pad) {
+        fn bin_remove_pre(&self, ts: u64, bin: &gst::Bin, element: &gst::Element) {
+            self.stream.set_time(ts);
+            self.pending_removals
+                .lock()
+                .unwrap()
+                .push((Entity::from_object(bin), Entity::from_object(element)));
+        }
+
+        fn bin_remove_post(&self, ts: u64, bin: &gst::Bin, success: bool) {
+            self.stream.set_time(ts);
+            let bin_id = Entity::from_object(bin);
+            let element_id = {
+                let mut pending = self.pending_removals.lock().unwrap();
+                let Some(index) = pending.iter().rposition(|(b, _)| *b == bin_id) else {
+                    warn!("No pending removal from {bin:?}");
+                    return;
+                };
+                pending.remove(index).1
+            };
+
+            // The element is either going to be destroyed or added to another
+            // bin, in both cases we will hear about it again. Meanwhile it is a
+            // top level element.
+            if success {
+                self.stream.insert_one(
+                    element_id,
+                    Child {
+                        parent: REGISTRY.session(),
+                    },
+                );
+            }
+        }
+
+        fn object_destroyed(&self, ts: u64, object: std::ptr::NonNull<gst::ffi::GstObject>) {
+            self.stream.set_time(ts);
+            // The object is being finalized so only its address can still be
+            // used. Objects that were never registered release nothing.
+            let mut stats = self.stats.lock().unwrap();
+            let mut latency = self.latency.lock().unwrap();
+            let mut queues = self.queues.lock().unwrap();
+            let mut cpu = self.cpu.lock().unwrap();
+            let mut watchdog = self.watchdog.lock().unwrap();
+            for id in REGISTRY.release(object.as_ptr() as usize) {
+                stats.forget(id);
+                latency.forget(id);
+                queues.forget(id);
+                cpu.forget(id);
+                watchdog.forget(id);
+                self.stream.despawn(id);
+            }
+        }
+
+        fn pad_link_pre(&self, ts: u64, src: &gst::Pad, sink: &gst::Pad) {
+            self.stream.set_time(ts);
             self.stream.insert_link(src, sink, State::Pending);
         }
 
         fn pad_link_post(
             &self,
-            _ts: u64,
+            ts: u64,
             src: &gst::Pad,
             sink: &gst::Pad,
             result: Result<gst::PadLinkSuccess, gst::PadLinkError>,
         ) {
+            self.stream.set_time(ts);
             let state = match result {
                 Ok(_) => State::Done,
                 Err(_) => State::Failed,
             };
 
             self.stream.insert_link(src, sink, state);
+            // Internal pads of ghost pads are only seen when linked
+            if state == State::Done {
+                self.stats.lock().unwrap().watch(src);
+                self.watchdog.lock().unwrap().watch_pad(src);
+            }
+        }
+
+        fn pad_unlink_post(&self, ts: u64, src: &gst::Pad, sink: &gst::Pad, success: bool) {
+            self.stream.set_time(ts);
+            if success {
+                if let Some(id) = REGISTRY.release_link(src, sink) {
+                    self.stream.despawn(id);
+                }
+                self.watchdog
+                    .lock()
+                    .unwrap()
+                    .unwatch_pad(Entity::from_object(src));
+            }
+        }
+
+        fn pad_push_pre(&self, ts: u64, pad: &gst::Pad, buffer: &gst::Buffer) {
+            self.stream.set_time(ts);
+            stats::record_buffer(pad, buffer);
+            latency::record_push(ts, pad, buffer);
+            watchdog::record_buffer(ts, pad);
+            self.record_thread(pad);
+        }
+
+        fn pad_push_list_pre(&self, ts: u64, pad: &gst::Pad, buffer_list: &gst::BufferList) {
+            self.stream.set_time(ts);
+            stats::record_buffer_list(pad, buffer_list);
+            latency::record_push_list(ts, pad, buffer_list);
+            watchdog::record_buffer(ts, pad);
+            self.record_thread(pad);
+        }
+
+        fn pad_push_event_pre(&self, ts: u64, pad: &gst::Pad, event: &gst::Event) {
+            self.stream.set_time(ts);
+            match event.view() {
+                gst::EventView::Caps(caps) => self.stream.insert_caps(pad, caps.caps()),
+                // Gaps stand for buffers on sparse streams
+                gst::EventView::Gap(_) => watchdog::record_buffer(ts, pad),
+                gst::EventView::Eos(_) => watchdog::record_eos(pad),
+                _ => {}
+            }
+        }
+
+        fn pad_pull_range_post(
+            &self,
+            ts: u64,
+            pad: &gst::Pad,
+            result: Result<&gst::Buffer, gst::FlowError>,
+        ) {
+            self.stream.set_time(ts);
+            // The sink pad is pulling, but we account the buffer on the source
+            // pad so that the stats are always found on the output port of links
+            if let (Ok(buffer), Some(peer)) = (result, pad.peer()) {
+                stats::record_buffer(&peer, buffer);
+                watchdog::record_buffer(ts, &peer);
+                self.record_thread(&peer);
+            }
         }
     }
 }
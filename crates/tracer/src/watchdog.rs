@@ -0,0 +1,251 @@
+use crate::{AttachExt, EntityExt};
+use gst::prelude::*;
+use hecs::Entity;
+use once_cell::sync::Lazy;
+use pipewerk_common::{StallReason, Stalled, State};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Time without buffers, or spent changing state, after which a pad or an
+/// element is flagged, in nanoseconds. Set in milliseconds with the
+/// `stall-timeout` tracer param.
+pub const DEFAULT_STALL_TIMEOUT: u64 = 5_000_000_000;
+
+static ACTIVITY: Lazy<glib::Quark> = Lazy::new(|| glib::Quark::from_str("pipewerk-activity"));
+
+/// Attached to the pads, so that their streaming threads record buffers
+/// without taking the watchdog
+#[derive(Default)]
+struct PadActivity {
+    last_buffer: AtomicU64,
+    // Pads are expected to go quiet after EOS, until buffers flow again
+    eos: AtomicBool,
+}
+
+struct WatchedPad {
+    element: Entity,
+    activity: Arc<PadActivity>,
+}
+
+struct WatchedElement {
+    element: glib::WeakRef<gst::Element>,
+    playing_since: Option<u64>,
+    pending_since: Option<(State, u64)>,
+}
+
+/// Flags the pads that stopped seeing buffers while their element is playing
+/// and the elements stuck in a state change
+pub struct Watchdog {
+    timeout: u64,
+    pads: HashMap<Entity, WatchedPad>,
+    elements: HashMap<Entity, WatchedElement>,
+    stalled: HashMap<Entity, Stalled>,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_STALL_TIMEOUT,
+            pads: HashMap::new(),
+            elements: HashMap::new(),
+            stalled: HashMap::new(),
+        }
+    }
+}
+
+impl Watchdog {
+    pub fn set_timeout(&mut self, timeout: u64) {
+        self.timeout = timeout;
+    }
+
+    pub fn watch(&mut self, element: &gst::Element) {
+        self.elements.insert(
+            Entity::from_object(element),
+            WatchedElement {
+                element: element.downgrade(),
+                playing_since: None,
+                pending_since: None,
+            },
+        );
+    }
+
+    pub fn forget(&mut self, id: Entity) {
+        self.pads.remove(&id);
+        self.elements.remove(&id);
+        self.stalled.remove(&id);
+    }
+
+    /// Watch a source pad from the time it is linked, so that an element
+    /// that never outputs anything is flagged once it has been playing long
+    /// enough
+    pub fn watch_pad(&mut self, pad: &gst::Pad) {
+        // The internal pads of ghost pads have no element, the pads they
+        // proxy are watched instead
+        let Some(element) = pad.parent_element() else {
+            return;
+        };
+        let activity = pad.attach(*ACTIVITY, Arc::<PadActivity>::default);
+        self.pads.insert(
+            Entity::from_object(pad),
+            WatchedPad {
+                element: Entity::from_object(&element),
+                activity: activity.clone(),
+            },
+        );
+    }
+
+    /// Stop watching a pad that was unlinked, its stall ends with the next
+    /// check
+    pub fn unwatch_pad(&mut self, pad: Entity) {
+        self.pads.remove(&pad);
+    }
+
+    pub fn set_playing(&mut self, ts: u64, element: Entity, playing: bool) {
+        if let Some(watched) = self.elements.get_mut(&element) {
+            watched.playing_since = playing.then(|| watched.playing_since.unwrap_or(ts));
+        }
+    }
+
+    /// Elements to check, their pending state is read without holding the
+    /// watchdog as reading it takes the lock of the element
+    pub fn elements(&self) -> Vec<(Entity, gst::Element)> {
+        self.elements
+            .iter()
+            .filter_map(|(&id, watched)| Some((id, watched.element.upgrade()?)))
+            .collect()
+    }
+
+    /// Check the pads and the elements against the timeout, given the state
+    /// each element is still changing to. Returns the stalls that started or
+    /// changed since the previous check, and `None` for the ones that ended.
+    pub fn check(
+        &mut self,
+        ts: u64,
+        pending: &[(Entity, Option<State>)],
+    ) -> Vec<(Entity, Option<Stalled>)> {
+        let mut stalled = HashMap::new();
+
+        for &(id, state) in pending {
+            let Some(watched) = self.elements.get_mut(&id) else {
+                continue;
+            };
+            watched.pending_since = state.map(|state| match watched.pending_since {
+                Some((previous, since)) if previous == state => (state, since),
+                _ => (state, ts),
+            });
+            if let Some((state, since)) = watched.pending_since {
+                if ts.saturating_sub(since) >= self.timeout {
+                    let reason = StallReason::PendingState(state);
+                    stalled.insert(id, Stalled { reason, since });
+                }
+            }
+        }
+
+        for (&id, pad) in &self.pads {
+            let playing_since = self
+                .elements
+                .get(&pad.element)
+                .and_then(|element| element.playing_since);
+            let activity = &pad.activity;
+            let eos = activity.eos.load(Ordering::Relaxed);
+            let Some(playing_since) = playing_since.filter(|_| !eos) else {
+                continue;
+            };
+            let since = activity
+                .last_buffer
+                .load(Ordering::Relaxed)
+                .max(playing_since);
+            if ts.saturating_sub(since) >= self.timeout {
+                let reason = StallReason::NoBuffers;
+                stalled.insert(id, Stalled { reason, since });
+            }
+        }
+
+        let ended = self
+            .stalled
+            .keys()
+            .filter(|id| !stalled.contains_key(*id))
+            .map(|&id| (id, None));
+        let changes = stalled
+            .iter()
+            .filter(|&(id, s)| self.stalled.get(id) != Some(s))
+            .map(|(&id, &s)| (id, Some(s)))
+            .chain(ended)
+            .collect();
+        self.stalled = stalled;
+        changes
+    }
+}
+
+/// Record a buffer seen on a pad, pads that are not watched are skipped
+pub fn record_buffer(ts: u64, pad: &gst::Pad) {
+    if let Some(activity) = pad.attached::<Arc<PadActivity>>(*ACTIVITY) {
+        activity.last_buffer.store(ts, Ordering::Relaxed);
+        activity.eos.store(false, Ordering::Relaxed);
+    }
+}
+
+pub fn record_eos(pad: &gst::Pad) {
+    if let Some(activity) = pad.attached::<Arc<PadActivity>>(*ACTIVITY) {
+        activity.eos.store(true, Ordering::Relaxed);
+    }
+}
+
+/// State each element is still changing to, if any
+pub fn read_pending(element: &gst::Element) -> Option<State> {
+    let state = element.pending_state();
+    (state != gst::State::VoidPending).then(|| state.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog() {
+        gst::init().unwrap();
+
+        let bin = gst::Bin::new();
+        let pad = gst::Pad::builder(gst::PadDirection::Src)
+            .name("src")
+            .build();
+        bin.add_pad(&pad).unwrap();
+        let (element, pad_id) = (Entity::from_object(&bin), Entity::from_object(&pad));
+
+        let mut watchdog = Watchdog::default();
+        watchdog.set_timeout(100);
+        watchdog.watch(bin.upcast_ref());
+        watchdog.watch_pad(&pad);
+
+        // Pads are only expected to see buffers while playing, from the
+        // time their element started to
+        assert!(watchdog.check(1000, &[]).is_empty());
+        watchdog.set_playing(1000, element, true);
+        assert!(watchdog.check(1050, &[]).is_empty());
+        let stall = Stalled {
+            reason: StallReason::NoBuffers,
+            since: 1000,
+        };
+        assert_eq!(watchdog.check(1100, &[]), vec![(pad_id, Some(stall))]);
+        assert!(watchdog.check(1200, &[]).is_empty());
+        record_buffer(1210, &pad);
+        assert_eq!(watchdog.check(1220, &[]), vec![(pad_id, None)]);
+
+        // Nor after EOS
+        record_eos(&pad);
+        assert!(watchdog.check(2000, &[]).is_empty());
+
+        let pending = [(element, Some(State::Paused))];
+        assert!(watchdog.check(2000, &pending).is_empty());
+        let stall = Stalled {
+            reason: StallReason::PendingState(State::Paused),
+            since: 2000,
+        };
+        assert_eq!(watchdog.check(2100, &pending), vec![(element, Some(stall))]);
+        assert_eq!(
+            watchdog.check(2200, &[(element, None)]),
+            vec![(element, None)]
+        );
+    }
+}
@@ -14,6 +14,8 @@ pub trait GraphStyle {
     fn port_stroke(&self, selected: bool) -> Stroke;
     fn port_radius(&self) -> f32;
     fn link_stroke(&self, selected: bool) -> Stroke;
+    fn stalled_stroke(&self) -> Stroke;
+    fn contains_stall_stroke(&self) -> Stroke;
 }
 
 impl GraphStyle for egui::Style {
@@ -80,4 +82,12 @@ impl GraphStyle for egui::Style {
             Stroke::new(1.0, color)
         }
     }
+
+    fn stalled_stroke(&self) -> Stroke {
+        Stroke::new(2.0, self.visuals.error_fg_color)
+    }
+
+    fn contains_stall_stroke(&self) -> Stroke {
+        Stroke::new(1.0, self.visuals.error_fg_color.gamma_multiply(0.6))
+    }
 }
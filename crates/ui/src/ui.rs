@@ -4,12 +4,16 @@ use egui::{self, collapsing_header::CollapsingState, Modifiers, Pos2, Rect, Vec2
 use egui_extras::{Column, TableBuilder};
 use pipewerk_common::{
     comps::*,
+    recording::save_recording,
     ser::{load_datastore, save_datastore},
-    DataStore, ViewMode,
+    Control, DataStore, Timestamp, ViewMode,
 };
 use hecs::Entity;
 use log::*;
-use std::{collections::HashSet, ops::Deref};
+use std::{
+    collections::HashSet,
+    ops::{Deref, RangeInclusive},
+};
 
 /// Helper function to sort entities by name, then by entity ID for consistent ordering
 fn sort_entities_by_name<I>(world: &hecs::World, entities: I) -> Vec<hecs::Entity>
@@ -39,12 +43,32 @@ where
 pub struct UiState {
     show_left_panel: bool,
     show_right_panel: bool,
+    show_messages_panel: bool,
     show_debug_window: bool,
+    color_by_thread: bool,
     size_tracker: hecs::ChangeTracker<Size>,
     tree_change_tracker: hecs::ChangeTracker<Child>,
     graph_change_tracker: hecs::ChangeTracker<Edge>,
     current_selection: Selection,
     scene_rect: Rect,
+    property_filter: String,
+    property_sort: PropertySort,
+    modified_properties_only: bool,
+    // Entity whose DOT graph is displayed
+    dot_graph: Option<Entity>,
+    issues_only: bool,
+    // Pads whose buffers are being sampled
+    sampled_pads: HashSet<Entity>,
+    // Texture of the video preview being displayed, with the pad and the
+    // pixels it was made from
+    preview_texture: Option<(Entity, Vec<u8>, egui::TextureHandle)>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum PropertySort {
+    #[default]
+    Name,
+    Value,
 }
 
 impl Default for UiState {
@@ -52,12 +76,21 @@ impl Default for UiState {
         Self {
             show_left_panel: true,
             show_right_panel: true,
+            show_messages_panel: false,
             show_debug_window: false,
+            color_by_thread: false,
             size_tracker: Default::default(),
             tree_change_tracker: Default::default(),
             graph_change_tracker: Default::default(),
             current_selection: Default::default(),
             scene_rect: Rect::ZERO,
+            property_filter: String::new(),
+            property_sort: Default::default(),
+            modified_properties_only: false,
+            dot_graph: None,
+            issues_only: false,
+            sampled_pads: HashSet::new(),
+            preview_texture: None,
         }
     }
 }
@@ -130,6 +163,10 @@ pub fn show_ui(
     show_top_menu(ctx, state, data_store);
     show_timeline(ctx, data_store);
 
+    if state.show_messages_panel {
+        show_messages(ctx, state, data_store);
+    }
+
     if state.show_left_panel && data_store.current_world().query::<&Node>().iter().count() > 0 {
         show_tree_view(ctx, state, data_store);
     }
@@ -138,6 +175,10 @@ pub fn show_ui(
         show_inspector(ctx, state, data_store);
     }
 
+    if let Some(entity) = state.dot_graph {
+        show_dot_graph_window(ctx, state, data_store, entity);
+    }
+
     // Query all child nodes, then follow links to parents to compute the max depth
     let mut max_depth = 1;
     for (entity, _) in data_store
@@ -250,6 +291,7 @@ pub fn show_ui(
                     }
 
                     // Draw the graphs and update the selected entity if needed
+                    let stalls = Stalls::new(data_store.current_world());
                     state.current_selection =
                         roots
                             .into_iter()
@@ -260,6 +302,8 @@ pub fn show_ui(
                                     root,
                                     zoom,
                                     state.current_selection,
+                                    state.color_by_thread,
+                                    &stalls,
                                 )
                                 .unwrap_or(Selection::None)
                                 .or(selected)
@@ -286,8 +330,6 @@ pub fn show_ui(
     if reorganise {
         ctx.memory_mut(|mem| mem.reset_areas());
     }
-
-    // FIXME: request a repaint if we are displaying a feed on a texture
 }
 
 fn show_top_menu(ctx: &egui::Context, state: &mut UiState, data_store: &mut DataStore) {
@@ -303,9 +345,10 @@ fn show_top_menu(ctx: &egui::Context, state: &mut UiState, data_store: &mut Data
                 let now = chrono::Local::now();
 
                 ui.menu_button("File", |ui| {
-                    let file_name = format!("pipewerk {}.ron", now.format("%Y-%m-%d %H.%M"));
+                    let file_name = format!("pipewerk {}.pwrk", now.format("%Y-%m-%d %H.%M"));
                     let dialog = rfd::FileDialog::new()
                         .set_file_name(&file_name)
+                        .add_filter("Pipewerk Recordings", &["pwrk"])
                         .add_filter("Pipewerk DataStore Files", &["ron"]);
 
                     if ui.button("Open...").clicked() {
@@ -333,7 +376,13 @@ fn show_top_menu(ctx: &egui::Context, state: &mut UiState, data_store: &mut Data
                     if ui.button("Save as...").clicked() {
                         if let Some(path) = dialog.save_file() {
                             info!("Saving datastore to {path:?}");
-                            match save_datastore(data_store, path) {
+                            // RON is kept as a readable export format
+                            let result = if path.extension().is_some_and(|e| e == "ron") {
+                                save_datastore(data_store, path)
+                            } else {
+                                save_recording(data_store, path)
+                            };
+                            match result {
                                 Ok(()) => {
                                     info!(
                                         "Successfully saved datastore with {} commands",
@@ -373,6 +422,19 @@ fn show_top_menu(ctx: &egui::Context, state: &mut UiState, data_store: &mut Data
                     {
                         state.show_right_panel = !state.show_right_panel;
                     }
+                    if ui
+                        .button(if state.show_messages_panel {
+                            "Hide Messages Panel"
+                        } else {
+                            "Show Messages Panel"
+                        })
+                        .clicked()
+                    {
+                        state.show_messages_panel = !state.show_messages_panel;
+                    }
+
+                    ui.checkbox(&mut state.color_by_thread, "Colour by Thread")
+                        .on_hover_text("Tint the elements with the thread pushing their output");
 
                     if ui
                         .button(if state.show_debug_window {
@@ -458,10 +520,16 @@ fn show_timeline(ctx: &egui::Context, data_store: &mut DataStore) {
                 // Show current position with better formatting
                 match (data_store.current_timeline_position(), is_rolling) {
                     (Some(position), true) => {
-                        ui.label(format!("Position: {} (Live)", position));
+                        ui.label(format!(
+                            "Position: {} (Live)",
+                            format_position(data_store, position)
+                        ));
                     }
                     (Some(position), false) => {
-                        ui.label(format!("Position: {} (Paused)", position));
+                        ui.label(format!(
+                            "Position: {} (Paused)",
+                            format_position(data_store, position)
+                        ));
                     }
                     (None, _) => {
                         ui.label("No commands recorded");
@@ -476,24 +544,261 @@ fn show_timeline(ctx: &egui::Context, data_store: &mut DataStore) {
                     .unwrap_or(*range.start());
                 let prev_position = current_position;
 
+                let world = data_store.current_world();
+                let stalls: Vec<(Timestamp, String)> = data_store
+                    .stalls(range.clone())
+                    .into_iter()
+                    .map(|(ts, entity, stalled)| {
+                        let source = match entity {
+                            Some(entity) => object_path(world, entity),
+                            None => "Removed object".to_string(),
+                        };
+                        (ts, format!("{source}: {}", stall_text(stalled)))
+                    })
+                    .collect();
+                let mut clicked_stall = None;
+
                 ui.vertical(|ui| {
                     ui.style_mut().spacing.slider_width = ui.available_width();
-                    ui.add(
-                        egui::Slider::new(&mut current_position, range)
+                    let response = ui.add(
+                        egui::Slider::new(&mut current_position, range.clone())
                             .clamping(egui::SliderClamping::Always)
                             .show_value(false)
                             .trailing_fill(true)
                             .handle_shape(egui::style::HandleShape::Rect { aspect_ratio: 0.5 }),
                     );
+                    clicked_stall = show_stall_markers(ui, response.rect, range.clone(), &stalls);
+                    show_time_axis(ui, response.rect, range);
                 });
 
-                if prev_position != current_position {
+                if let Some(ts) = clicked_stall {
+                    data_store.set_view(ViewMode::Specific(ts));
+                } else if prev_position != current_position {
                     data_store.set_view(ViewMode::Specific(current_position));
                 }
             }
         });
 }
 
+/// Draw second ticks under the timeline slider, which spans `slider_rect`
+fn show_time_axis(ui: &mut egui::Ui, slider_rect: Rect, range: RangeInclusive<Timestamp>) {
+    let (rect, _) =
+        ui.allocate_exact_size(Vec2::new(slider_rect.width(), 14.0), egui::Sense::hover());
+    let (start, end) = (*range.start(), *range.end());
+    if end <= start {
+        return;
+    }
+
+    // The slider keeps its handle inside the rail, as egui does
+    let x_range = slider_rect.x_range().shrink(slider_rect.height() / 2.5);
+    let span = (end - start) as f64;
+
+    // Pick 1, 2 or 5 times a power of ten, for about ten ticks at most
+    let min_step = span / 10.0;
+    let magnitude = 10f64.powf(min_step.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|&s| s >= min_step)
+        .unwrap_or(min_step);
+    let decimals = (9.0 - step.log10().floor()).max(0.0) as usize;
+
+    let painter = ui.painter();
+    let color = ui.visuals().weak_text_color();
+    let mut tick = (start as f64 / step).ceil() * step;
+    while tick <= end as f64 {
+        let x = x_range.min + ((tick - start as f64) / span) as f32 * x_range.span();
+        painter.vline(
+            x,
+            rect.top()..=rect.top() + 3.0,
+            egui::Stroke::new(1.0, color),
+        );
+        painter.text(
+            Pos2::new(x, rect.top() + 3.0),
+            egui::Align2::CENTER_TOP,
+            format!("{:.*} s", decimals, tick / 1e9),
+            egui::FontId::proportional(9.0),
+            color,
+        );
+        tick += step;
+    }
+}
+
+/// Mark the stalls flagged by the watchdog on the timeline slider, returns the
+/// time of the marker clicked if any
+fn show_stall_markers(
+    ui: &mut egui::Ui,
+    slider_rect: Rect,
+    range: RangeInclusive<Timestamp>,
+    stalls: &[(Timestamp, String)],
+) -> Option<Timestamp> {
+    let (start, end) = (*range.start(), *range.end());
+    if end <= start {
+        return None;
+    }
+
+    let x_range = slider_rect.x_range().shrink(slider_rect.height() / 2.5);
+    let span = (end - start) as f64;
+    let stroke = ui.style().stalled_stroke();
+
+    let mut clicked = None;
+    for (index, (ts, text)) in stalls.iter().enumerate() {
+        let x = x_range.min + ((ts - start) as f64 / span) as f32 * x_range.span();
+        ui.painter().vline(x, slider_rect.y_range(), stroke);
+
+        let marker = Rect::from_center_size(
+            Pos2::new(x, slider_rect.center().y),
+            Vec2::new(6.0, slider_rect.height()),
+        );
+        let response = ui
+            .interact(marker, ui.id().with(("stall", index)), egui::Sense::click())
+            .on_hover_text(format!("{}\n{text}", format_nanoseconds(*ts)));
+        if response.clicked() {
+            clicked = Some(*ts);
+        }
+    }
+    clicked
+}
+
+fn stall_text(stalled: &Stalled) -> String {
+    match stalled.reason {
+        StallReason::NoBuffers => "No buffers while playing".to_string(),
+        StallReason::PendingState(state) => format!("Stuck changing to {state:?}"),
+    }
+}
+
+/// Name of an object, prefixed with the name of its element for pads
+fn object_path(world: &hecs::World, entity: Entity) -> String {
+    let name = |entity| world.get::<&Name>(entity).map(|n| n.0.clone()).ok();
+    let own = name(entity).unwrap_or_else(|| format!("{entity:?}"));
+    let parent = world
+        .get::<&Port>(entity)
+        .ok()
+        .and_then(|_| world.parent(entity))
+        .and_then(name);
+    match parent {
+        Some(parent) => format!("{parent}:{own}"),
+        None => own,
+    }
+}
+
+/// Format a timeline position, with the matching local time when the origin
+/// of the recording is known
+fn format_position(data_store: &DataStore, position: Timestamp) -> String {
+    let Some(origin) = data_store.wall_clock_origin else {
+        return format_nanoseconds(position);
+    };
+    let wall_clock = chrono::DateTime::from_timestamp_nanos((origin + position) as i64)
+        .with_timezone(&chrono::Local);
+    format!(
+        "{} ({})",
+        format_nanoseconds(position),
+        wall_clock.format("%H:%M:%S%.3f")
+    )
+}
+
+/// Messages posted on the bus up to the current position of the timeline
+fn show_messages(ctx: &egui::Context, state: &mut UiState, data_store: &DataStore) {
+    let frame =
+        egui::Frame::side_top_panel(ctx.style().as_ref()).inner_margin(egui::Margin::same(6));
+
+    egui::TopBottomPanel::bottom("messages")
+        .resizable(true)
+        .default_height(150.0)
+        .frame(frame)
+        .show(ctx, |ui| {
+            let messages = match data_store.current_timeline_position() {
+                Some(position) => data_store.bus_messages(..=position),
+                None => Vec::new(),
+            };
+            let messages: Vec<_> = messages
+                .into_iter()
+                .filter(|(_, _, message)| {
+                    !state.issues_only
+                        || matches!(message.kind, MessageKind::Error | MessageKind::Warning)
+                })
+                .collect();
+
+            ui.horizontal(|ui| {
+                ui.strong("Messages");
+                ui.label(format!("{}", messages.len()));
+                ui.checkbox(&mut state.issues_only, "Errors and warnings only");
+            });
+
+            TableBuilder::new(ui)
+                .id_salt("messages_table")
+                .striped(true)
+                .stick_to_bottom(true)
+                .column(Column::auto().at_least(100.0))
+                .column(Column::auto().at_least(80.0))
+                .column(Column::auto().at_least(120.0))
+                .column(Column::remainder())
+                .body(|body| {
+                    body.rows(18.0, messages.len(), |mut row| {
+                        let (ts, entity, message) = messages[row.index()];
+                        row.col(|ui| {
+                            ui.label(format_nanoseconds(ts));
+                        });
+                        row.col(|ui| {
+                            ui.label(message_kind_text(ui, message.kind));
+                        });
+                        row.col(|ui| match entity {
+                            Some(entity) => {
+                                if ui.link(&message.source).clicked() {
+                                    state.current_selection = Selection::Entity(entity);
+                                }
+                            }
+                            None => {
+                                ui.weak(&message.source);
+                            }
+                        });
+                        row.col(|ui| {
+                            let r = ui.label(&message.text);
+                            if let Some(details) = &message.details {
+                                r.on_hover_text(details);
+                            }
+                        });
+                    });
+                });
+        });
+}
+
+fn message_kind_text(ui: &egui::Ui, kind: MessageKind) -> egui::RichText {
+    let text = egui::RichText::new(kind.to_string());
+    match kind {
+        MessageKind::Error => text.color(ui.visuals().error_fg_color),
+        MessageKind::Warning => text.color(ui.visuals().warn_fg_color),
+        _ => text,
+    }
+}
+
+/// Count of the errors and warnings posted by an element, the last one is
+/// shown on hover
+fn show_message_badges(ui: &mut egui::Ui, log: &MessageLog, font: egui::FontId) {
+    let badges = [
+        ("✖", log.errors, ui.visuals().error_fg_color),
+        ("⚠", log.warnings, ui.visuals().warn_fg_color),
+    ];
+    for (icon, count, color) in badges {
+        if count == 0 {
+            continue;
+        }
+        let text = egui::RichText::new(format!("{icon} {count}"))
+            .font(font.clone())
+            .color(color);
+        let r = ui.add(egui::Label::new(text).selectable(false));
+        if let Some(issue) = &log.last_issue {
+            r.on_hover_ui(|ui| {
+                ui.label(message_kind_text(ui, issue.kind).strong());
+                ui.label(&issue.text);
+                if let Some(details) = &issue.details {
+                    ui.weak(details);
+                }
+            });
+        }
+    }
+}
+
 fn show_tree_view(ctx: &egui::Context, state: &mut UiState, data_store: &mut DataStore) {
     let frame =
         egui::Frame::side_top_panel(ctx.style().as_ref()).inner_margin(egui::Margin::same(10));
@@ -624,6 +929,28 @@ fn show_inspector(ctx: &egui::Context, state: &mut UiState, data_store: &mut Dat
                         });
                     }
 
+                    if let Ok(kind) = data_store.current_world().get::<&Kind>(selected) {
+                        body.row(18.0, |mut row| {
+                            row.col(|ui| {
+                                ui.label("Kind");
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{kind}"));
+                            });
+                        });
+                    }
+
+                    if let Ok(label) = data_store.current_world().get::<&Label>(selected) {
+                        body.row(18.0, |mut row| {
+                            row.col(|ui| {
+                                ui.label("Label");
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{label}"));
+                            });
+                        });
+                    }
+
                     if let Ok(state) = data_store.current_world().get::<&State>(selected) {
                         body.row(18.0, |mut row| {
                             row.col(|ui| {
@@ -634,10 +961,308 @@ fn show_inspector(ctx: &egui::Context, state: &mut UiState, data_store: &mut Dat
                             });
                         });
                     }
+
+                    if let Ok(latency) = data_store.current_world().get::<&QueriedLatency>(selected)
+                    {
+                        body.row(18.0, |mut row| {
+                            row.col(|ui| {
+                                ui.label("Latency");
+                            });
+                            row.col(|ui| {
+                                ui.label(format_latency(&latency));
+                            });
+                        });
+                    }
+
+                    let world = data_store.current_world();
+                    let thread = world
+                        .get::<&Thread>(selected)
+                        .map(|t| t.thread)
+                        .ok()
+                        .or_else(|| element_thread(world, selected));
+                    if let Some(thread) = thread {
+                        let mut name = match world.get::<&ThreadInfo>(thread) {
+                            Ok(info) => format!("{} ({})", info.name, info.tid),
+                            Err(_) => format!("{thread:?}"),
+                        };
+                        if let Ok(usage) = world.get::<&CpuUsage>(thread) {
+                            name.push_str(&format!(", {:.1}% CPU", usage.percent));
+                        }
+                        body.row(18.0, |mut row| {
+                            row.col(|ui| {
+                                ui.label("Thread");
+                            });
+                            row.col(|ui| {
+                                ui.label(egui::RichText::new(name).color(thread_color(thread)));
+                            });
+                        });
+                    }
+
+                    if let Ok(stalled) = world.get::<&Stalled>(selected) {
+                        body.row(18.0, |mut row| {
+                            row.col(|ui| {
+                                ui.label("Stalled");
+                            });
+                            row.col(|ui| {
+                                ui.colored_label(ui.visuals().error_fg_color, stall_text(&stalled));
+                            });
+                        });
+                    }
+
+                    if let Ok(usage) = world.get::<&CpuUsage>(selected) {
+                        body.row(18.0, |mut row| {
+                            row.col(|ui| {
+                                ui.label("CPU");
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.1}%", usage.percent))
+                                    .on_hover_text(format!(
+                                        "{} used so far",
+                                        format_duration(usage.total)
+                                    ));
+                            });
+                        });
+                    }
+
+                    if let Ok(session) = data_store.current_world().get::<&Session>(selected) {
+                        let connected_at =
+                            chrono::DateTime::from_timestamp_nanos(session.connected_at as i64)
+                                .with_timezone(&chrono::Local);
+                        let rows = [
+                            ("Process", session.process_name.clone()),
+                            ("PID", session.pid.to_string()),
+                            ("Host", session.host.clone()),
+                            (
+                                "Connected",
+                                connected_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                            ),
+                        ];
+                        for (label, value) in rows {
+                            body.row(18.0, |mut row| {
+                                row.col(|ui| {
+                                    ui.label(label);
+                                });
+                                row.col(|ui| {
+                                    ui.label(value);
+                                });
+                            });
+                        }
+                    }
+
+                    if let Ok(stats) = data_store.current_world().get::<&PadStats>(selected) {
+                        let rows = [
+                            ("Buffers", stats.buffers.to_string()),
+                            ("Bytes", format_bytes(stats.bytes as f64)),
+                            ("Buffer rate", format!("{:.1}/s", stats.buffers_per_sec)),
+                            (
+                                "Byte rate",
+                                format!("{}/s", format_bytes(stats.bytes_per_sec)),
+                            ),
+                            (
+                                "Last PTS",
+                                stats
+                                    .last_pts
+                                    .map(format_nanoseconds)
+                                    .unwrap_or_else(|| "none".to_string()),
+                            ),
+                        ];
+                        for (label, value) in rows {
+                            body.row(18.0, |mut row| {
+                                row.col(|ui| {
+                                    ui.label(label);
+                                });
+                                row.col(|ui| {
+                                    ui.label(value);
+                                });
+                            });
+                        }
+                    }
+
+                    let world = data_store.current_world();
+                    let latencies = [
+                        (
+                            "Processing latency",
+                            world.get::<&ProcessingLatency>(selected).map(|l| l.0),
+                        ),
+                        (
+                            "End-to-end latency",
+                            world.get::<&EndToEndLatency>(selected).map(|l| l.0),
+                        ),
+                    ];
+                    for (label, stats) in latencies {
+                        let Ok(stats) = stats else {
+                            continue;
+                        };
+                        body.row(18.0, |mut row| {
+                            row.col(|ui| {
+                                ui.label(label);
+                            });
+                            row.col(|ui| {
+                                ui.label(format!(
+                                    "{} mean, {} p99",
+                                    format_duration(stats.mean),
+                                    format_duration(stats.p99)
+                                ))
+                                .on_hover_text(format!(
+                                    "{} to {} over {} buffers",
+                                    format_duration(stats.min),
+                                    format_duration(stats.max),
+                                    stats.samples
+                                ));
+                            });
+                        });
+                    }
                 });
 
+            // Controls only make sense on the latest state of a connected process
+            let live = matches!(data_store.current_view_mode, ViewMode::Rolling)
+                && data_store.is_live(selected);
+            let is_element = data_store
+                .current_world()
+                .satisfies::<(&TypeName, &State)>(selected)
+                .unwrap_or(false);
+            if live && is_element {
+                ui.add_space(10.0);
+                show_element_controls(ui, data_store, selected);
+            }
+
+            let is_pad = data_store
+                .current_world()
+                .satisfies::<&Port>(selected)
+                .unwrap_or(false);
+            if live && is_pad {
+                ui.add_space(10.0);
+                let mut sampling = state.sampled_pads.contains(&selected);
+                if ui
+                    .checkbox(&mut sampling, "Preview buffers")
+                    .on_hover_text(
+                        "Sample the buffers going through the pad a few times per second",
+                    )
+                    .changed()
+                {
+                    if sampling {
+                        state.sampled_pads.insert(selected);
+                    } else {
+                        state.sampled_pads.remove(&selected);
+                    }
+                    data_store.control(selected, Control::SamplePad(sampling));
+                }
+            }
+
+            if let Ok(preview) = data_store.current_world().get::<&Preview>(selected) {
+                ui.add_space(10.0);
+                ui.strong("Preview");
+                show_preview(ui, state, selected, &preview);
+            }
+
+            // Graphs are kept in the history, they can be shown at any time
+            if data_store
+                .current_world()
+                .satisfies::<&DotGraph>(selected)
+                .unwrap_or(false)
+                && ui.button("Show DOT graph").clicked()
+            {
+                state.dot_graph = Some(selected);
+            }
+
+            if let Ok(attributes) = data_store.current_world().get::<&Attributes>(selected) {
+                ui.add_space(10.0);
+                TableBuilder::new(ui)
+                    .id_salt("attributes_table")
+                    .column(Column::auto().at_least(100.0))
+                    .column(Column::remainder())
+                    .header(20.0, |mut header| {
+                        header.col(|ui| {
+                            ui.strong("Attribute");
+                        });
+                        header.col(|ui| {
+                            ui.strong("Value");
+                        });
+                    })
+                    .body(|mut body| {
+                        for (key, attribute) in attributes.0.iter() {
+                            body.row(18.0, |mut row| {
+                                row.col(|ui| {
+                                    ui.label(key);
+                                });
+                                row.col(|ui| {
+                                    ui.label(attribute.to_string());
+                                });
+                            });
+                        }
+                    });
+            }
+
+            if let Ok(caps) = data_store.current_world().get::<&Caps>(selected) {
+                ui.add_space(10.0);
+                ui.strong("Caps");
+                for (index, structure) in caps.0.iter().enumerate() {
+                    egui::CollapsingHeader::new(&structure.name)
+                        .id_salt(("caps_structure", index))
+                        .default_open(index == 0)
+                        .show(ui, |ui| {
+                            TableBuilder::new(ui)
+                                .id_salt(("caps_fields", index))
+                                .column(Column::auto().at_least(100.0))
+                                .column(Column::remainder())
+                                .body(|mut body| {
+                                    for (field, value) in structure.fields.iter() {
+                                        body.row(18.0, |mut row| {
+                                            row.col(|ui| {
+                                                ui.label(field);
+                                            });
+                                            row.col(|ui| {
+                                                ui.label(value);
+                                            });
+                                        });
+                                    }
+                                });
+                        });
+                }
+            }
+
+            let mut edits = Vec::new();
             if let Ok(properties) = data_store.current_world().get::<&Properties>(selected) {
                 ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut state.property_filter)
+                            .hint_text("Filter properties")
+                            .desired_width(120.0),
+                    );
+                    ui.checkbox(&mut state.modified_properties_only, "Modified only");
+                });
+
+                let filter = state.property_filter.to_lowercase();
+                let mut rows: Vec<(&String, &Property)> = properties
+                    .0
+                    .iter()
+                    .filter(|(key, _)| key.to_lowercase().contains(&filter))
+                    .filter(|(_, property)| {
+                        !state.modified_properties_only || !property.is_default()
+                    })
+                    .collect();
+                if state.property_sort == PropertySort::Value {
+                    // Numeric values first, in ascending order, then the others by text
+                    rows.sort_by(
+                        |(_, a), (_, b)| match (a.value.as_f64(), b.value.as_f64()) {
+                            (Some(a), Some(b)) => a.total_cmp(&b),
+                            (Some(_), None) => std::cmp::Ordering::Less,
+                            (None, Some(_)) => std::cmp::Ordering::Greater,
+                            (None, None) => a.value.to_string().cmp(&b.value.to_string()),
+                        },
+                    );
+                }
+
+                // Properties changed at the current position of the timeline
+                let changed: HashSet<&str> = data_store
+                    .current_timeline_position()
+                    .map(|position| data_store.property_changes(selected, position..=position))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(_, change)| change.name.as_str())
+                    .collect();
+
                 egui::ScrollArea::horizontal()
                     .id_salt("properties_table_scroll_area")
                     .show(ui, |ui| {
@@ -645,28 +1270,431 @@ fn show_inspector(ctx: &egui::Context, state: &mut UiState, data_store: &mut Dat
                             .column(Column::auto().at_least(100.0))
                             .column(Column::remainder())
                             .header(20.0, |mut header| {
-                                header.col(|ui| {
-                                    ui.strong("Property");
-                                });
-                                header.col(|ui| {
-                                    ui.strong("Value");
-                                });
+                                for (title, sort) in [
+                                    ("Property", PropertySort::Name),
+                                    ("Value", PropertySort::Value),
+                                ] {
+                                    header.col(|ui| {
+                                        let selected = state.property_sort == sort;
+                                        let text = egui::RichText::new(title).strong();
+                                        if ui.selectable_label(selected, text).clicked() {
+                                            state.property_sort = sort;
+                                        }
+                                    });
+                                }
                             })
                             .body(|mut body| {
-                                for (key, value) in properties.0.iter() {
-                                    body.row(18.0, |mut row| {
+                                let row_height = if live { 20.0 } else { 18.0 };
+                                for (key, property) in rows {
+                                    body.row(row_height, |mut row| {
                                         row.col(|ui| {
-                                            ui.label(key);
+                                            ui.label(key)
+                                                .on_hover_ui(|ui| show_param_spec(ui, property));
                                         });
                                         row.col(|ui| {
-                                            ui.label(value);
+                                            let mut text =
+                                                egui::RichText::new(property.value.to_string());
+                                            if !property.is_default() {
+                                                text =
+                                                    text.strong().color(ui.visuals().warn_fg_color);
+                                            }
+                                            if changed.contains(key.as_str()) {
+                                                text = text.background_color(
+                                                    ui.visuals().selection.bg_fill,
+                                                );
+                                            }
+
+                                            let editable = live
+                                                && property.spec.writable
+                                                && !property.spec.construct_only;
+                                            let edit = editable
+                                                .then(|| edit_property(ui, key, property))
+                                                .flatten();
+                                            match edit {
+                                                Some(Some(value)) => {
+                                                    edits.push((key.clone(), value))
+                                                }
+                                                Some(None) => {}
+                                                None => {
+                                                    ui.label(text).on_hover_ui(|ui| {
+                                                        show_property_changes(
+                                                            ui, data_store, selected, key,
+                                                        )
+                                                    });
+                                                }
+                                            }
                                         });
                                     });
                                 }
                             });
                     });
             }
+
+            for (name, value) in edits {
+                data_store.control(
+                    selected,
+                    Control::SetProperty(PropertyChange { name, value }),
+                );
+            }
+        });
+}
+
+/// State changes and events that can be requested on an element
+fn show_element_controls(ui: &mut egui::Ui, data_store: &mut DataStore, element: Entity) {
+    let current = data_store
+        .current_world()
+        .get::<&State>(element)
+        .map(|state| *state)
+        .ok();
+
+    ui.horizontal_wrapped(|ui| {
+        for state in [State::Null, State::Ready, State::Paused, State::Playing] {
+            if ui
+                .selectable_label(current == Some(state), format!("{state:?}"))
+                .clicked()
+            {
+                data_store.control(element, Control::SetState(state));
+            }
+        }
+        ui.separator();
+        if ui
+            .button("EOS")
+            .on_hover_text("Send an end of stream event")
+            .clicked()
+        {
+            data_store.control(element, Control::SendEos);
+        }
+        if ui
+            .button("Flush")
+            .on_hover_text("Flush the data in flight")
+            .clicked()
+        {
+            data_store.control(element, Control::Flush);
+        }
+    });
+
+    ui.horizontal_wrapped(|ui| {
+        let world = data_store.current_world();
+        let is_bin = world
+            .children(element)
+            .into_iter()
+            .any(|child| world.satisfies::<&Node>(child).unwrap_or(false));
+        if is_bin
+            && ui
+                .button("Dump DOT")
+                .on_hover_text("Get the graph of the bin as seen by GStreamer")
+                .clicked()
+        {
+            data_store.control(element, Control::DumpDot);
+        }
+        if ui.button("Query latency").clicked() {
+            data_store.control(element, Control::QueryLatency);
+        }
+    });
+}
+
+/// Latest sample of the buffers going through a pad
+fn show_preview(ui: &mut egui::Ui, state: &mut UiState, pad: Entity, preview: &Preview) {
+    // Previews are refreshed as they come, without any other interaction
+    ui.ctx().request_repaint();
+
+    match preview {
+        Preview::Video {
+            width,
+            height,
+            rgba,
+        } => {
+            let size = [*width as usize, *height as usize];
+            if rgba.len() != size[0] * size[1] * 4 {
+                ui.label("Invalid frame");
+                return;
+            }
+            let image = egui::ColorImage::from_rgba_unmultiplied(size, rgba);
+            let texture = match &mut state.preview_texture {
+                Some((entity, pixels, texture)) if *entity == pad => {
+                    if pixels != rgba {
+                        texture.set(image, Default::default());
+                        *pixels = rgba.clone();
+                    }
+                    texture.clone()
+                }
+                _ => {
+                    let texture = ui.ctx().load_texture("preview", image, Default::default());
+                    state.preview_texture = Some((pad, rgba.clone(), texture.clone()));
+                    texture
+                }
+            };
+            ui.image((texture.id(), texture.size_vec2()));
+        }
+        Preview::Audio { channels, peaks } => {
+            let channels = (*channels as usize).max(1);
+            let slices = peaks.len() / channels;
+            let channel_height = 40.0;
+            let (rect, _) = ui.allocate_exact_size(
+                Vec2::new(ui.available_width(), channel_height * channels as f32),
+                egui::Sense::hover(),
+            );
+            let painter = ui.painter_at(rect);
+            let stroke = egui::Stroke::new(1.0, ui.visuals().text_color());
+            for channel in 0..channels {
+                let center = rect.top() + channel_height * (channel as f32 + 0.5);
+                for slice in 0..slices {
+                    let half = peaks[slice * channels + channel] * channel_height / 2.0;
+                    let x = rect.left() + rect.width() * (slice as f32 + 0.5) / slices as f32;
+                    painter.line_segment(
+                        [Pos2::new(x, center - half), Pos2::new(x, center + half)],
+                        stroke,
+                    );
+                }
+            }
+        }
+        Preview::Data { size, head } => {
+            ui.label(format!("{} buffer", format_bytes(*size as f64)));
+            let dump = head
+                .chunks(16)
+                .enumerate()
+                .map(|(line, bytes)| {
+                    let bytes: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+                    format!("{:04x}  {}", line * 16, bytes.join(" "))
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            ui.label(egui::RichText::new(dump).monospace());
+        }
+    }
+}
+
+fn format_latency(latency: &QueriedLatency) -> String {
+    let max = latency
+        .max
+        .map(format_duration)
+        .unwrap_or_else(|| "unbounded".to_string());
+    let live = if latency.live { "live" } else { "not live" };
+    format!("{} to {max}, {live}", format_duration(latency.min))
+}
+
+fn show_dot_graph_window(
+    ctx: &egui::Context,
+    state: &mut UiState,
+    data_store: &DataStore,
+    entity: Entity,
+) {
+    let world = data_store.current_world();
+    let Ok(graph) = world.get::<&DotGraph>(entity) else {
+        state.dot_graph = None;
+        return;
+    };
+    let title = match world.get::<&Name>(entity) {
+        Ok(name) => format!("DOT graph of {}", *name),
+        Err(_) => "DOT graph".to_string(),
+    };
+
+    let mut open = true;
+    egui::Window::new(title)
+        .id(egui::Id::new("dot_graph_window"))
+        .open(&mut open)
+        .default_size([600.0, 400.0])
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Copy").clicked() {
+                    ctx.copy_text(graph.0.clone());
+                }
+                if ui.button("Save as...").clicked() {
+                    let dialog = rfd::FileDialog::new()
+                        .set_file_name("graph.dot")
+                        .add_filter("DOT graphs", &["dot"]);
+                    if let Some(path) = dialog.save_file() {
+                        if let Err(e) = std::fs::write(&path, &graph.0) {
+                            error!("Failed to save the graph to {path:?}: {e}");
+                        }
+                    }
+                }
+            });
+            egui::ScrollArea::both().show(ui, |ui| {
+                ui.add(egui::TextEdit::multiline(&mut graph.0.as_str()).code_editor());
+            });
         });
+
+    if !open {
+        state.dot_graph = None;
+    }
+}
+
+/// Editor of a writable property. Returns `None` for values that can't be
+/// edited, and the new value once the property has been edited.
+fn edit_property(
+    ui: &mut egui::Ui,
+    name: &str,
+    property: &Property,
+) -> Option<Option<PropertyValue>> {
+    let range = property.spec.range.as_ref();
+    let edited = match &property.value {
+        PropertyValue::Bool(v) => {
+            let mut v = *v;
+            ui.checkbox(&mut v, "")
+                .changed()
+                .then_some(PropertyValue::Bool(v))
+        }
+        PropertyValue::Int(v) => {
+            let range = match range {
+                Some((PropertyValue::Int(min), PropertyValue::Int(max))) => Some(*min..=*max),
+                _ => None,
+            };
+            edit_number(ui, name, *v, range, 1.0).map(PropertyValue::Int)
+        }
+        PropertyValue::UInt(v) => {
+            let range = match range {
+                Some((PropertyValue::UInt(min), PropertyValue::UInt(max))) => Some(*min..=*max),
+                _ => None,
+            };
+            edit_number(ui, name, *v, range, 1.0).map(PropertyValue::UInt)
+        }
+        PropertyValue::Float(v) => {
+            let range = match range {
+                Some((PropertyValue::Float(min), PropertyValue::Float(max))) => Some(*min..=*max),
+                _ => None,
+            };
+            edit_number(ui, name, *v, range, 0.01).map(PropertyValue::Float)
+        }
+        PropertyValue::Enum { value, nick } => {
+            let mut selected = nick.clone();
+            egui::ComboBox::from_id_salt(("property", name))
+                .selected_text(nick)
+                .show_ui(ui, |ui| {
+                    for choice in property.spec.choices.iter() {
+                        ui.selectable_value(&mut selected, choice.clone(), choice);
+                    }
+                });
+            // The tracer looks the value up by its nick
+            (selected != *nick).then_some(PropertyValue::Enum {
+                value: *value,
+                nick: selected,
+            })
+        }
+        PropertyValue::String(v) => edit_text(ui, name, v.as_deref().unwrap_or_default())
+            .map(|text| PropertyValue::String(Some(text))),
+        // Sent as text, to be parsed by the tracer
+        PropertyValue::Flags { .. }
+        | PropertyValue::Structure(Some(_))
+        | PropertyValue::Other(_) => {
+            edit_text(ui, name, &property.value.to_string()).map(PropertyValue::Other)
+        }
+        _ => return None,
+    };
+    Some(edited)
+}
+
+/// Drag editor that returns the number once the drag is released or the typed
+/// value validated, rather than on every frame of the drag
+fn edit_number<T>(
+    ui: &mut egui::Ui,
+    name: &str,
+    current: T,
+    range: Option<RangeInclusive<T>>,
+    speed: f64,
+) -> Option<T>
+where
+    T: egui::emath::Numeric + Send + Sync,
+{
+    let id = ui.id().with(("property_number", name));
+    let mut value = ui
+        .data_mut(|data| data.get_temp::<T>(id))
+        .unwrap_or(current);
+
+    let mut drag = egui::DragValue::new(&mut value).speed(speed);
+    if let Some(range) = range {
+        drag = drag.range(range);
+    }
+    let response = ui.add(drag);
+
+    // The value follows the property unless it is being edited, the property
+    // only changes once the value is sent back
+    let done = response.drag_stopped() || response.lost_focus();
+    if done || !(response.dragged() || response.has_focus()) {
+        ui.data_mut(|data| data.remove::<T>(id));
+    } else {
+        ui.data_mut(|data| data.insert_temp(id, value));
+    }
+    (done && value != current).then_some(value)
+}
+
+/// Single line editor that returns the text once validated with enter
+fn edit_text(ui: &mut egui::Ui, name: &str, current: &str) -> Option<String> {
+    let id = ui.id().with(("property_text", name));
+    let mut text = ui
+        .data_mut(|data| data.get_temp::<String>(id))
+        .unwrap_or_else(|| current.to_string());
+
+    let response = ui.add(egui::TextEdit::singleline(&mut text).desired_width(f32::INFINITY));
+    // The text follows the value unless it is being edited
+    if response.has_focus() {
+        ui.data_mut(|data| data.insert_temp(id, text.clone()));
+    } else {
+        ui.data_mut(|data| data.remove::<String>(id));
+    }
+
+    let validated = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+    (validated && text != current).then_some(text)
+}
+
+/// Last changes of a property up to the current position of the timeline
+fn show_property_changes(ui: &mut egui::Ui, data_store: &DataStore, entity: Entity, name: &str) {
+    const MAX_CHANGES: usize = 10;
+
+    let end = data_store
+        .current_timeline_position()
+        .unwrap_or(Timestamp::MAX);
+    let changes: Vec<_> = data_store
+        .property_changes(entity, ..=end)
+        .into_iter()
+        .filter(|(_, change)| change.name == name)
+        .collect();
+    if changes.is_empty() {
+        ui.weak("Not changed since it was recorded");
+        return;
+    }
+
+    ui.strong("Changes");
+    for (ts, change) in changes.iter().rev().take(MAX_CHANGES) {
+        ui.label(format!(
+            "{}: {}",
+            format_position(data_store, *ts),
+            change.value
+        ));
+    }
+    if changes.len() > MAX_CHANGES {
+        ui.weak(format!("and {} earlier", changes.len() - MAX_CHANGES));
+    }
+}
+
+fn show_param_spec(ui: &mut egui::Ui, property: &Property) {
+    let spec = &property.spec;
+    if let Some(blurb) = &spec.blurb {
+        ui.label(blurb);
+    }
+    ui.label(format!("Type: {}", spec.type_name));
+    if let Some(default) = &spec.default {
+        ui.label(format!("Default: {default}"));
+    }
+    if let Some((min, max)) = &spec.range {
+        ui.label(format!("Range: {min} to {max}"));
+    }
+    if !spec.choices.is_empty() {
+        ui.label(format!("Values: {}", spec.choices.join(", ")));
+    }
+
+    let flags: Vec<&str> = [
+        (spec.readable, "readable"),
+        (spec.writable, "writable"),
+        (spec.construct_only, "construct only"),
+        (spec.controllable, "controllable"),
+    ]
+    .into_iter()
+    .filter_map(|(set, flag)| set.then_some(flag))
+    .collect();
+    if !flags.is_empty() {
+        ui.weak(flags.join(", "));
+    }
 }
 
 #[cfg(debug_assertions)]
@@ -754,6 +1782,7 @@ fn create_link_shapes(
     children: &[hecs::Entity],
     zoom: f32,
     current_selection: Selection,
+    stalls: &Stalls,
 ) -> egui::Shape {
     use pipewerk_common::State;
 
@@ -801,7 +1830,12 @@ fn create_link_shapes(
 
         let selected = current_selection == Selection::Entity(edge.output_port)
             || current_selection == Selection::Entity(edge.input_port);
-        let stroke = style.link_stroke(selected).zoomed(zoom);
+        // Links downstream of stalled pads show where the flow stopped
+        let stroke = if stalls.links.contains(&entity) && !selected {
+            style.stalled_stroke().zoomed(zoom)
+        } else {
+            style.link_stroke(selected).zoomed(zoom)
+        };
 
         // Always tessellate the bezier curve into line segments
         let bezier_points = compute_bezier_points(*from, *to, 0.5);
@@ -810,6 +1844,35 @@ fn create_link_shapes(
         // Check the state to determine styling
         let state = world.get::<&State>(entity).ok();
 
+        let middle_pos = curve_points.get(curve_points.len() / 2).copied();
+
+        // Label the link with its media type and the data rate measured on its output port
+        let mut label_lines = Vec::new();
+        if let Ok(caps) = world.get::<&Caps>(entity) {
+            label_lines.push(caps.short_label());
+        }
+        if let Ok(stats) = world.get::<&PadStats>(edge.output_port) {
+            label_lines.push(format!(
+                "{:.0} buf/s · {}/s",
+                stats.buffers_per_sec,
+                format_bytes(stats.bytes_per_sec)
+            ));
+        }
+        if let Some(middle_pos) = middle_pos.filter(|_| !label_lines.is_empty()) {
+            let label = label_lines.join("\n");
+            let label_shape = ui.ctx().fonts(|fonts| {
+                epaint::Shape::text(
+                    fonts,
+                    middle_pos - Vec2::new(0.0, 4.0 * zoom),
+                    egui::Align2::CENTER_BOTTOM,
+                    label,
+                    egui::FontId::proportional(9.0 * zoom),
+                    style.visuals.weak_text_color(),
+                )
+            });
+            shapes.push(label_shape);
+        }
+
         match state.map(|s| *s) {
             Some(State::Pending) => {
                 // Apply dotted styling for pending links
@@ -821,9 +1884,6 @@ fn create_link_shapes(
             }
             Some(State::Failed) => {
                 // Apply solid line with cross glyph in middle for failed links
-                let middle_index = curve_points.len() / 2;
-                let middle_pos = curve_points.get(middle_index).copied();
-
                 let solid_shapes = epaint::Shape::line(curve_points, stroke);
                 shapes.push(solid_shapes);
 
@@ -868,6 +1928,8 @@ fn show_node(
     entity: hecs::Entity,
     zoom: f32,
     current_selection: Selection,
+    color_by_thread: bool,
+    stalls: &Stalls,
 ) -> Result<Selection> {
     let style = ui.ctx().style();
     let mut proposed_selection = Selection::None;
@@ -927,11 +1989,36 @@ fn show_node(
         }
     }
 
+    // Bins hold elements of several threads, only the elements are tinted
+    let tint = color_by_thread
+        .then(|| element_thread(world, entity))
+        .flatten()
+        .filter(|_| children.is_empty())
+        .map(thread_color);
+    let bg_color = match tint {
+        Some(color) => style.node_bg_color().lerp_to_gamma(color, 0.4),
+        None => style.node_bg_color(),
+    };
+
+    // Collapsed bins hide the stalls of their children
+    let collapsed = collapsing_state
+        .as_ref()
+        .is_some_and(|state| !state.is_open());
+    let stroke = if selected {
+        style.node_stroke(selected)
+    } else if stalls.origins.contains(&entity) {
+        style.stalled_stroke()
+    } else if collapsed && stalls.containing.contains(&entity) {
+        style.contains_stall_stroke()
+    } else {
+        style.node_stroke(selected)
+    };
+
     let mut prepared_frame = egui::Frame::default()
         .corner_radius(style.node_corner_radius())
         .inner_margin(style.node_padding())
-        .stroke(style.node_stroke(selected))
-        .fill(style.node_bg_color())
+        .stroke(stroke)
+        .fill(bg_color)
         .shadow(if is_root {
             style.node_shadow()
         } else {
@@ -963,11 +2050,53 @@ fn show_node(
                     _ => "??",
                 };
                 ui.add(
-                    egui::Label::new(egui::RichText::new(state_label).font(font)).selectable(false),
+                    egui::Label::new(egui::RichText::new(state_label).font(font.clone()))
+                        .selectable(false),
                 );
             }
+            if let Ok(log) = world.get::<&MessageLog>(entity) {
+                show_message_badges(ui, &log, font);
+            }
         });
 
+        if let Ok(label) = world.get::<&Label>(entity) {
+            let font = egui::TextStyle::Small.resolve(ui.style()).zoomed(zoom);
+            ui.add(
+                egui::Label::new(egui::RichText::new(&label.0).font(font).weak()).selectable(false),
+            );
+        }
+
+        // Latencies are shown on the nodes to spot the elements adding delay
+        let latencies = [
+            ("⏱", world.get::<&ProcessingLatency>(entity).map(|l| l.0)),
+            ("⇥", world.get::<&EndToEndLatency>(entity).map(|l| l.0)),
+        ];
+        for (icon, stats) in latencies {
+            if let Ok(stats) = stats {
+                let font = egui::TextStyle::Small.resolve(ui.style()).zoomed(zoom);
+                let text = format!("{icon} {}", format_duration(stats.mean));
+                ui.add(egui::Label::new(egui::RichText::new(text).font(font)).selectable(false));
+            }
+        }
+
+        if let Ok(stalled) = world.get::<&Stalled>(entity) {
+            let font = egui::TextStyle::Small.resolve(ui.style()).zoomed(zoom);
+            let text = egui::RichText::new(format!("⚠ {}", stall_text(&stalled)))
+                .font(font)
+                .color(ui.visuals().error_fg_color);
+            ui.add(egui::Label::new(text).selectable(false));
+        }
+
+        if let Ok(usage) = world.get::<&CpuUsage>(entity) {
+            let font = egui::TextStyle::Small.resolve(ui.style()).zoomed(zoom);
+            let text = format!("⚙ {:.0}%", usage.percent);
+            ui.add(egui::Label::new(egui::RichText::new(text).font(font)).selectable(false));
+        }
+
+        if let Ok(level) = world.get::<&QueueLevel>(entity) {
+            show_queue_gauge(ui, &level, zoom);
+        }
+
         if let Some(mut state) = collapsing_state {
             state.show_body_unindented(ui, |ui| {
                 let where_to_put_links = ui.painter().add(egui::Shape::Noop);
@@ -979,14 +2108,23 @@ fn show_node(
                         .iter()
                         .cloned()
                         .fold(proposed_selection, |selected, child| {
-                            show_node(ui, world, child, zoom, current_selection)
-                                .inspect_err(|e| error!("{e:?}"))
-                                .unwrap_or(Selection::None)
-                                .or(selected)
+                            show_node(
+                                ui,
+                                world,
+                                child,
+                                zoom,
+                                current_selection,
+                                color_by_thread,
+                                stalls,
+                            )
+                            .inspect_err(|e| error!("{e:?}"))
+                            .unwrap_or(Selection::None)
+                            .or(selected)
                         });
 
                 // Draw the links
-                let link_shapes = create_link_shapes(ui, world, &children, zoom, current_selection);
+                let link_shapes =
+                    create_link_shapes(ui, world, &children, zoom, current_selection, stalls);
                 ui.painter().set(where_to_put_links, link_shapes);
             });
         }
@@ -994,10 +2132,10 @@ fn show_node(
 
     let r = prepared_frame.allocate_space(&mut child_ui);
 
-    prepared_frame.frame.fill = if r.hovered() {
-        style.node_bg_hover_color()
-    } else {
-        style.node_bg_color()
+    prepared_frame.frame.fill = match (r.hovered(), tint) {
+        (true, Some(_)) => bg_color.lerp_to_gamma(style.visuals.window_fill, 0.8),
+        (true, None) => style.node_bg_hover_color(),
+        (false, _) => bg_color,
     };
 
     let r = ui.allocate_rect(child_ui.min_rect(), egui::Sense::hover());
@@ -1031,6 +2169,159 @@ fn show_node(
     Ok(proposed_selection)
 }
 
+/// Where the stalls are shown in the graph
+struct Stalls {
+    /// Elements at the origin of the stalls: the ones owning a stalled pad
+    /// and the ones stuck changing state
+    origins: HashSet<Entity>,
+    /// Bins holding an element at the origin of a stall
+    containing: HashSet<Entity>,
+    /// Links downstream of the stalled pads, that the flow no longer reaches
+    links: HashSet<Entity>,
+}
+
+impl Stalls {
+    fn new(world: &hecs::World) -> Self {
+        let mut origins = HashSet::new();
+        let mut pads = Vec::new();
+        for (entity, _) in world.query::<&Stalled>().iter() {
+            match world.satisfies::<&Port>(entity) {
+                Ok(true) => {
+                    pads.push(entity);
+                    origins.extend(world.parent(entity));
+                }
+                _ => {
+                    origins.insert(entity);
+                }
+            }
+        }
+
+        // Bins wait for their children to change state, only the innermost
+        // elements stuck are kept
+        let mut containing = HashSet::new();
+        for &entity in origins.iter() {
+            let mut parent = world.parent(entity);
+            while let Some(bin) = parent.filter(|bin| containing.insert(*bin)) {
+                parent = world.parent(bin);
+            }
+        }
+        origins.retain(|entity| !containing.contains(entity));
+
+        // Follow the links from the stalled pads, through the elements they
+        // lead to and out of their source pads
+        let edges: Vec<(Entity, Edge)> = world
+            .query::<&Edge>()
+            .iter()
+            .map(|(entity, edge)| (entity, edge.clone()))
+            .collect();
+        let mut links = HashSet::new();
+        let mut visited: HashSet<Entity> = pads.iter().cloned().collect();
+        while let Some(pad) = pads.pop() {
+            for (entity, edge) in edges.iter().filter(|(_, e)| e.output_port == pad) {
+                links.insert(*entity);
+                let Some(element) = world.parent(edge.input_port) else {
+                    continue;
+                };
+                let outputs = world
+                    .query::<(&Child, &Port)>()
+                    .iter()
+                    .filter(|(_, (child, port))| child.parent == element && **port == Port::Output)
+                    .map(|(pad, _)| pad)
+                    .collect::<Vec<_>>();
+                pads.extend(outputs.into_iter().filter(|pad| visited.insert(*pad)));
+            }
+        }
+
+        Self {
+            origins,
+            containing,
+            links,
+        }
+    }
+}
+
+/// Thread pushing buffers out of an element, or into it for sinks
+fn element_thread(world: &hecs::World, element: Entity) -> Option<Entity> {
+    let pads: Vec<(Entity, Port)> = world
+        .query::<(&Child, &Port)>()
+        .iter()
+        .filter(|(_, (child, _))| child.parent == element)
+        .map(|(pad, (_, port))| (pad, *port))
+        .collect();
+    let thread_of = |pad: Entity| world.get::<&Thread>(pad).ok().map(|t| t.thread);
+
+    pads.iter()
+        .filter(|(_, port)| *port == Port::Output)
+        .find_map(|&(pad, _)| thread_of(pad))
+        .or_else(|| {
+            world
+                .query::<&Edge>()
+                .iter()
+                .filter(|(_, edge)| pads.iter().any(|&(pad, _)| pad == edge.input_port))
+                .find_map(|(_, edge)| thread_of(edge.output_port))
+        })
+}
+
+/// Distinct colour for each thread
+fn thread_color(thread: Entity) -> egui::Color32 {
+    // Steps of the golden ratio spread the hues of consecutive ids
+    let hue = (thread.id() as f32 * 0.618_034).fract();
+    egui::ecolor::Hsva::new(hue, 0.6, 0.6, 1.0).into()
+}
+
+/// Queues that stay full or empty for this long are highlighted, in nanoseconds
+const QUEUE_ALERT_DELAY: u64 = 2_000_000_000;
+
+/// Fill level of a queue, full queues point at a slow consumer and empty ones
+/// at a slow producer
+fn show_queue_gauge(ui: &mut egui::Ui, level: &QueueLevel, zoom: f32) {
+    let (rect, response) =
+        ui.allocate_exact_size(Vec2::new(80.0, 6.0) * zoom, egui::Sense::hover());
+    let visuals = ui.visuals();
+    let color = if level.full_for >= QUEUE_ALERT_DELAY {
+        visuals.error_fg_color
+    } else if level.empty_for >= QUEUE_ALERT_DELAY {
+        visuals.warn_fg_color
+    } else {
+        visuals.selection.bg_fill
+    };
+
+    let painter = ui.painter();
+    let radius = rect.height() / 2.0;
+    painter.rect_filled(rect, radius, visuals.extreme_bg_color);
+    let mut filled = rect;
+    filled.set_width(rect.width() * level.fill() as f32);
+    painter.rect_filled(filled, radius, color);
+
+    response.on_hover_ui(|ui| {
+        let limit = |value: String, max: u64, format: fn(u64) -> String| match max {
+            0 => value,
+            max => format!("{value} / {}", format(max)),
+        };
+        ui.label(limit(
+            format!("{} buffers", level.buffers),
+            level.max_buffers,
+            |v| v.to_string(),
+        ));
+        ui.label(limit(
+            format_bytes(level.bytes as f64),
+            level.max_bytes,
+            |v| format_bytes(v as f64),
+        ));
+        ui.label(limit(
+            format_duration(level.time),
+            level.max_time,
+            format_duration,
+        ));
+        if level.full_for > 0 {
+            ui.label(format!("Full for {}", format_duration(level.full_for)));
+        }
+        if level.empty_for > 0 {
+            ui.label(format!("Empty for {}", format_duration(level.empty_for)));
+        }
+    });
+}
+
 fn show_ports(
     ui: &mut egui::Ui,
     world: &mut hecs::World,
@@ -1067,11 +2358,16 @@ fn show_ports(
         let selected = current_selection.map_entity_or(false, |s| s == parent || s == entity);
 
         let pos = top.lerp(bottom, (index as f32 + 1.0) / (entities.len() as f32 + 1.0));
+        let stroke = if !selected && world.satisfies::<&Stalled>(entity).unwrap_or(false) {
+            s.stalled_stroke()
+        } else {
+            s.port_stroke(selected)
+        };
         painter.circle(
             pos,
             s.port_radius() * zoom,
             s.port_bg_fill(),
-            s.port_stroke(selected).zoomed(zoom),
+            stroke.zoomed(zoom),
         );
 
         let response = ui.interact(
@@ -1089,6 +2385,47 @@ fn show_ports(
     proposed_selection
 }
 
+/// Format a byte count with a binary unit prefix
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{value:.0} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Format a short duration in nanoseconds with the most readable unit
+fn format_duration(ns: u64) -> String {
+    if ns < 1_000 {
+        format!("{ns} ns")
+    } else if ns < 1_000_000 {
+        format!("{:.1} µs", ns as f64 / 1e3)
+    } else if ns < 1_000_000_000 {
+        format!("{:.1} ms", ns as f64 / 1e6)
+    } else {
+        format!("{:.2} s", ns as f64 / 1e9)
+    }
+}
+
+/// Format a duration in nanoseconds the way gstreamer prints clock times
+fn format_nanoseconds(ns: u64) -> String {
+    let seconds = ns / 1_000_000_000;
+    format!(
+        "{}:{:02}:{:02}.{:09}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60,
+        ns % 1_000_000_000
+    )
+}
+
 fn compute_bezier_points(from: Pos2, to: Pos2, curvature: f32) -> [Pos2; 4] {
     let dx = to.x - from.x;
     let control_x_offset = dx * curvature;